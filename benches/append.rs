@@ -0,0 +1,58 @@
+#![feature(test)]
+
+extern crate test;
+use test::Bencher;
+
+extern crate anyvec;
+use anyvec::*;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts allocations and reallocations, to check how often `append` grows the buffers.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn filled() -> AnyVec {
+    let mut vec = AnyVec::new();
+    for i in 0..5000u64 {
+        vec.push(i);
+    }
+    vec
+}
+
+#[bench]
+fn append(b: &mut Bencher) {
+    b.iter(|| {
+        let mut vec = AnyVec::new();
+        for i in 0..8u8 {
+            vec.push(i);
+        }
+        let mut other = filled();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        vec.append(&mut other);
+        // One for the data and one for the metadata.
+        assert!(ALLOCATIONS.load(Ordering::Relaxed) - allocations <= 2);
+        vec
+    });
+}
@@ -18,6 +18,7 @@ fn get(b: &mut Bencher) {
 }
 
 #[bench]
+#[allow(clippy::same_item_push)]
 fn std_vec_get(b: &mut Bencher) {
     let mut vec = Vec::new();
     for _ in 0..1000 {
@@ -36,6 +36,70 @@ fn push(b: &mut Bencher) {
     });
 }
 
+#[bench]
+fn insert_slice(b: &mut Bencher) {
+    let values = [0u32; 1000];
+    b.iter(|| {
+        let mut vec = AnyVec::new();
+        vec.push("Test");
+        vec.insert_slice(0, &values);
+    });
+}
+
+#[bench]
+fn insert_slice_by_insert(b: &mut Bencher) {
+    let values = [0u32; 1000];
+    b.iter(|| {
+        let mut vec = AnyVec::new();
+        vec.push("Test");
+        for value in values.iter() {
+            vec.insert(0, *value);
+        }
+    });
+}
+
+#[bench]
+fn from_slice(b: &mut Bencher) {
+    let values: Vec<u64> = (0..10_000).collect();
+    b.iter(|| AnyVec::from_slice(&values));
+}
+
+#[bench]
+fn from_slice_by_push(b: &mut Bencher) {
+    let values: Vec<u64> = (0..10_000).collect();
+    b.iter(|| {
+        let mut vec = AnyVec::new();
+        for value in values.iter() {
+            vec.push(*value);
+        }
+        vec
+    });
+}
+
+#[bench]
+fn prepend(b: &mut Bencher) {
+    b.iter(|| {
+        let mut vec = AnyVec::new();
+        vec.push("Test");
+        let mut other = AnyVec::new();
+        for _ in 0..500 {
+            other.push(0u32);
+        }
+        vec.prepend(&mut other);
+    });
+}
+
+#[bench]
+fn prepend_by_insert(b: &mut Bencher) {
+    b.iter(|| {
+        let mut vec = AnyVec::new();
+        vec.push("Test");
+        for _ in 0..500 {
+            vec.insert(0, 0u32);
+        }
+    });
+}
+
 #[bench]
 fn std_vec_insert_front(b: &mut Bencher) {
     b.iter(|| {
@@ -56,6 +120,7 @@ fn std_vec_insert_middle(b: &mut Bencher) {
 }
 
 #[bench]
+#[allow(clippy::same_item_push)]
 fn std_vec_push(b: &mut Bencher) {
     b.iter(|| {
         let mut vec = Vec::new();
@@ -0,0 +1,27 @@
+#![feature(test)]
+
+extern crate test;
+use test::Bencher;
+
+extern crate anyvec;
+use anyvec::*;
+
+#[bench]
+fn map_in_place(b: &mut Bencher) {
+    let mut vec = AnyVec::new();
+    for i in 0..10_000 {
+        vec.push(i as f64);
+    }
+    b.iter(|| vec.map_in_place(|x: &mut f64| *x *= 2.0));
+}
+
+#[bench]
+fn map_by_get_mut(b: &mut Bencher) {
+    let mut vec = AnyVec::new();
+    for i in 0..10_000 {
+        vec.push(i as f64);
+    }
+    b.iter(|| for i in 0..vec.len() {
+        *vec.get_mut::<f64>(i).unwrap().unwrap() *= 2.0;
+    });
+}
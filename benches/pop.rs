@@ -0,0 +1,34 @@
+#![feature(test)]
+
+extern crate test;
+use test::Bencher;
+
+extern crate anyvec;
+use anyvec::*;
+
+#[bench]
+fn pop(b: &mut Bencher) {
+    b.iter(|| {
+        let mut vec = AnyVec::with_capacity(1000, 16);
+        for _ in 0..1000 {
+            vec.push("Test");
+        }
+        while let Some(s) = vec.pop::<&str>().unwrap() {
+            test::black_box(s);
+        }
+    });
+}
+
+#[bench]
+fn pop_by_remove(b: &mut Bencher) {
+    b.iter(|| {
+        let mut vec = AnyVec::with_capacity(1000, 16);
+        for _ in 0..1000 {
+            vec.push("Test");
+        }
+        while !vec.is_empty() {
+            let index = vec.len() - 1;
+            test::black_box(vec.remove_and_return::<&str>(index).unwrap());
+        }
+    });
+}
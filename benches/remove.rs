@@ -0,0 +1,36 @@
+#![feature(test)]
+
+extern crate test;
+use test::Bencher;
+
+extern crate anyvec;
+use anyvec::*;
+
+fn filled() -> AnyVec {
+    let mut vec = AnyVec::new();
+    for i in 0..500u32 {
+        vec.push(i);
+        vec.push(u64::from(i));
+    }
+    vec
+}
+
+#[bench]
+fn remove_indices(b: &mut Bencher) {
+    let indices: Vec<usize> = (0..100).map(|i| i * 9 + 7).collect();
+    b.iter(|| {
+        let mut vec = filled();
+        test::black_box(vec.remove_indices(&indices));
+    });
+}
+
+#[bench]
+fn remove_indices_by_remove(b: &mut Bencher) {
+    let indices: Vec<usize> = (0..100).map(|i| i * 9 + 7).collect();
+    b.iter(|| {
+        let mut vec = filled();
+        for &index in indices.iter().rev() {
+            vec.remove(index);
+        }
+    });
+}
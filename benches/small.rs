@@ -0,0 +1,20 @@
+#![feature(test)]
+
+extern crate test;
+use test::Bencher;
+
+extern crate anyvec;
+use anyvec::*;
+
+// Compare `cargo bench --bench small` with `cargo bench --bench small --features smallvec`.
+#[bench]
+fn new_small(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..1000u32 {
+            let mut vec = AnyVec::new();
+            vec.push(i);
+            vec.push(u64::from(i));
+            test::black_box(&vec);
+        }
+    });
+}
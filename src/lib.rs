@@ -9,515 +9,5308 @@
 //!
 //! It can store anything that implements the `Any` trait.
 
+//!
+//! # Features
+//! - `smallvec`: stores the metadata of up to four elements inline, so small vectors only
+//!   allocate for their data. `AnyVecN` sets another number of inline elements.
+//! - `parallel`: adds `par_map_in_place`, which maps the elements of one type on several
+//!   threads.
+
 #![doc(html_root_url = "http://lschmierer.github.io/anyvec/")]
 
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
+
 use std::result::Result;
-use std::cmp;
+use std::cmp::{self, Ordering};
+use std::collections::{HashMap, TryReserveError};
+use std::alloc::{self, Layout};
 use std::any::{Any, TypeId};
-use std::mem;
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop, MaybeUninit};
+use std::ops::{Deref, Range};
 use std::ptr;
+use std::slice;
+#[cfg(feature = "parallel")]
+use std::thread;
 
-#[derive(Debug)]
-struct AnyMeta {
-    data_index: usize,
-    type_id: TypeId,
-    type_size: usize,
+/// Errors returned by the checked accessors of `AnyVec`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnyVecError {
+    /// The index `index` is out of bounds for a vector of length `len`.
+    OutOfBounds { index: usize, len: usize },
+    /// The element is of type `found`, but `expected` was requested.
+    TypeMismatch { expected: TypeId, found: TypeId },
+    /// The index `index` was requested more than once.
+    DuplicateIndex(usize),
+    /// The element at index `index` was not pushed as cloneable.
+    NotCloneable(usize),
+    /// A sequence has length `found`, but length `expected` was required.
+    LengthMismatch { expected: usize, found: usize },
+    /// The elements from `start` to `end` are separated by padding.
+    NotContiguous { start: usize, end: usize },
+    /// A type has size `found`, but size `expected` was required.
+    SizeMismatch { expected: usize, found: usize },
+    /// The element at index `index` is not aligned to `align`.
+    Misaligned { index: usize, align: usize },
+    /// An element has size `size`, but the vector only accepts elements up to size `max`.
+    TooLarge { size: usize, max: usize },
 }
 
-/// A growable list type with dynamic typing.
+impl fmt::Display for AnyVecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AnyVecError::OutOfBounds { index, len } => {
+                write!(f, "index {} out of bounds for length {}", index, len)
+            }
+            AnyVecError::TypeMismatch { expected, found } => {
+                write!(f, "invalid type {:?}, expected {:?}", expected, found)
+            }
+            AnyVecError::DuplicateIndex(index) => write!(f, "index {} requested twice", index),
+            AnyVecError::NotCloneable(index) => write!(f, "element {} is not cloneable", index),
+            AnyVecError::LengthMismatch { expected, found } => {
+                write!(f, "invalid length {}, expected {}", found, expected)
+            }
+            AnyVecError::NotContiguous { start, end } => write!(
+                f,
+                "elements {}..{} are separated by padding, try `compact`",
+                start, end
+            ),
+            AnyVecError::SizeMismatch { expected, found } => {
+                write!(f, "invalid size {}, expected {}", found, expected)
+            }
+            AnyVecError::Misaligned { index, align } => {
+                write!(f, "element {} is not aligned to {}", index, align)
+            }
+            AnyVecError::TooLarge { size, max } => {
+                write!(f, "element of size {} exceeds the maximum size {}", size, max)
+            }
+        }
+    }
+}
+
+impl Error for AnyVecError {}
+
+/// How the data buffer of an `AnyVec` grows when it runs out of room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Growth {
+    /// Grows the buffer geometrically, like `Vec`.
+    #[default]
+    Double,
+    /// Grows the buffer by at least the given number of bytes at a time.
+    Fixed(usize),
+    /// Grows the buffer by exactly the bytes needed.
+    Exact,
+}
+
+/// An element of one of two types, as yielded by `AnyVec::iter_types`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Either<L, R> {
+    /// An element of the first type.
+    Left(L),
+    /// An element of the second type.
+    Right(R),
+}
+
+/// A byte buffer whose start is aligned to the largest alignment of the elements it holds.
 ///
-/// It can store anything that implements the `Any` trait.
+/// `Vec` only aligns bytes to 1, so `buf` keeps `align - 1` bytes of slack in its capacity,
+/// and the data starts at `offset`, the first byte aligned to `align`.
 #[derive(Debug)]
-pub struct AnyVec {
-    data: Vec<u8>,
-    meta: Vec<AnyMeta>,
+struct AnyData {
+    buf: Vec<MaybeUninit<u8>>,
+    offset: usize,
+    align: usize,
+    growth: Growth,
 }
 
-impl AnyVec {
-    /// Constructs a new, empty `AnyVec`.
-    pub fn new() -> Self {
-        AnyVec {
-            data: Vec::new(),
-            meta: Vec::new(),
+impl AnyData {
+    fn new() -> Self {
+        AnyData {
+            buf: Vec::new(),
+            offset: 0,
+            align: 1,
+            growth: Growth::Double,
         }
     }
 
-    /// Constructs a new, empty `AnyVec` with specified capacity.
-    ///
-    /// Since we do not type sizes ahead, an average type size `avg_type_size` must be specified.
-    pub fn with_capacity(capacity: usize, avg_type_size: usize) -> Self {
-        AnyVec {
-            data: Vec::with_capacity(capacity * avg_type_size),
-            meta: Vec::with_capacity(capacity),
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        let mut bytes = ManuallyDrop::new(bytes);
+        let buf = unsafe {
+            Vec::from_raw_parts(
+                bytes.as_mut_ptr() as *mut MaybeUninit<u8>,
+                bytes.len(),
+                bytes.capacity(),
+            )
+        };
+        AnyData {
+            buf,
+            offset: 0,
+            align: 1,
+            growth: Growth::Double,
         }
     }
 
-    /// Returns the number of elements the vector can hold without reallocating.
-    pub fn capacity(&self, type_size: usize) -> usize {
-        cmp::min(self.meta.capacity(), self.data.capacity() / type_size)
+    fn with_capacity(capacity: usize, align: usize) -> Self {
+        let mut data = AnyData {
+            buf: Vec::with_capacity(capacity + align - 1),
+            offset: 0,
+            align,
+            growth: Growth::Double,
+        };
+        data.realign();
+        data
     }
 
-    /// Reserves capacity for at least `additional` more elements.
-    ///
-    /// Since we do not type sizes ahead, an average type size `avg_type_size` must be specified.
-    ///
-    /// # Panics
-    /// Panics if the new capacity overflows `usize`.
-    pub fn reserve(&mut self, additional: usize, avg_type_size: usize) {
-        self.data.reserve(additional * avg_type_size);
-        self.meta.reserve(additional);
+    fn len(&self) -> usize {
+        self.buf.len() - self.offset
     }
 
-    /// Reserves capacity for exactly `additional` more elements.
-    ///
-    /// Since we do not type sizes ahead, an average type size `avg_type_size` must be specified.
-    ///
-    /// # Panics
-    /// Panics if the new capacity overflows `usize`.
-    pub fn reserve_exact(&mut self, additional: usize, avg_type_size: usize) {
-        self.data.reserve_exact(additional * avg_type_size);
-        self.meta.reserve_exact(additional);
+    /// Returns the number of bytes that fit without reallocating, wherever the next
+    /// reallocation places the buffer.
+    fn capacity(&self) -> usize {
+        self.buf.capacity().saturating_sub(self.align - 1)
     }
 
-    /// Shrinks the capacity of the vector as much as possible.
-    pub fn shrink_to_fit(&mut self) {
-        self.data.shrink_to_fit();
-        self.meta.shrink_to_fit();
+    fn align(&self) -> usize {
+        self.align
     }
 
-    /// Shortens the vector to be `len` elements long.
-    pub fn truncate(&mut self, len: usize) {
-        self.data.truncate(match self.meta.get(len) {
-            Some(meta) => meta.data_index,
-            None => return,
-        });
-        self.meta.truncate(len);
+    fn as_ptr(&self) -> *const u8 {
+        unsafe { self.buf.as_ptr().add(self.offset) as *const u8 }
     }
 
-    /// Inserts an element at position `index` in the vector.
-    ///
-    /// Shifts elements after position `index` to the right.
-    ///
-    /// # Panics
-    /// Panics if `index` is greater than the vector's length.
-    pub fn insert<T: Any>(&mut self, index: usize, element: T) {
-        let type_id = TypeId::of::<T>();
-        let type_size = mem::size_of::<T>();
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        unsafe { self.buf.as_mut_ptr().add(self.offset) as *mut u8 }
+    }
 
-        let data_index = match self.meta.get(index) {
-            Some(meta) => meta.data_index,
-            None => self.data.len(),
-        };
+    unsafe fn set_len(&mut self, len: usize) {
+        self.buf.set_len(self.offset + len);
+    }
 
-        for i in index..self.meta.len() {
-            self.meta[i].data_index += type_size;
+    /// Raises the alignment of the buffer to at least `align`.
+    fn align_to(&mut self, align: usize) {
+        if align > self.align {
+            self.align = align;
+            self.reserve(0);
+            self.realign();
         }
-        self.meta.insert(
-            index,
-            AnyMeta {
-                data_index: data_index,
-                type_id: type_id,
-                type_size: type_size,
-            },
-        );
+    }
 
-        self.data.reserve(type_size);
+    /// Raises the alignment of the buffer to at least `align` and reserves `additional` bytes,
+    /// reallocating at most once.
+    fn reserve_aligned(&mut self, align: usize, additional: usize) {
+        self.align = cmp::max(self.align, align);
+        self.reserve(additional);
+    }
 
-        unsafe {
-            ptr::copy(
-                self.data.as_mut_ptr().offset(data_index as isize),
-                self.data.as_mut_ptr().offset(
-                    (data_index + type_size) as isize,
-                ),
-                self.data.len() - data_index,
-            );
-            ptr::copy(
-                &element as *const _ as *const _,
-                self.data.as_mut_ptr().offset(data_index as isize),
-                type_size,
-            );
-            let new_len = self.data.len() + type_size;
-            self.data.set_len(new_len);
+    fn reserve(&mut self, additional: usize) {
+        let bytes = additional + self.align - 1 - self.offset;
+        match self.growth {
+            Growth::Double => self.buf.reserve(bytes),
+            Growth::Fixed(step) => {
+                if bytes > self.buf.capacity() - self.buf.len() {
+                    self.buf.reserve_exact(cmp::max(bytes, step));
+                }
+            }
+            Growth::Exact => self.buf.reserve_exact(bytes),
         }
+        self.realign();
     }
 
-    /// Removes and returns the element at position `index`.
-    ///
-    /// Shifts elements after position `index` to the left.
-    ///
-    /// # Panics
-    /// Panics if `index` is out of bounds.
-    pub fn remove_and_return<T: Any>(&mut self, index: usize) -> Result<T, String> {
-        let type_id = self.meta[index].type_id;
-        let type_size = self.meta[index].type_size;
-        let data_index = self.meta[index].data_index;
+    fn reserve_exact(&mut self, additional: usize) {
+        self.buf.reserve_exact(additional + self.align - 1 - self.offset);
+        self.realign();
+    }
 
-        if type_id != TypeId::of::<T>() {
-            return Err(format!(
-                "invalid type {:?}, expected {:?}",
-                TypeId::of::<T>(),
-                &self.meta[self.meta.len() - 1].type_id
-            ));
+    fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        // A saturated byte count exceeds any capacity, so `Vec` reports the overflow.
+        let bytes = additional.saturating_add(self.align - 1 - self.offset);
+        self.buf.try_reserve(bytes)?;
+        self.realign();
+        Ok(())
+    }
+
+    /// Shrinks the capacity to `len` exactly.
+    ///
+    /// Frees the buffer if it is empty, resetting the alignment, since an unallocated buffer
+    /// only aligns to 1.
+    fn shrink_to_fit(&mut self) {
+        if self.len() == 0 {
+            self.buf = Vec::new();
+            self.offset = 0;
+            self.align = 1;
+            return;
+        }
+        let capacity = self.len() + self.align - 1;
+        if self.buf.capacity() != capacity {
+            // Unlike `shrink_to`, `with_capacity` guarantees the exact capacity.
+            let mut buf = Vec::with_capacity(capacity);
+            unsafe {
+                ptr::copy_nonoverlapping(self.as_ptr(), buf.as_mut_ptr() as *mut u8, self.len());
+                buf.set_len(self.len());
+            }
+            self.buf = buf;
+            self.offset = 0;
+            self.realign();
         }
+    }
 
-        self.meta.remove(index);
-        for i in index..self.meta.len() {
-            self.meta[i].data_index -= type_size;
+    /// Moves the data to the first aligned byte, after `buf` may have been reallocated.
+    ///
+    /// Requires the capacity of `buf` to include the slack.
+    fn realign(&mut self) {
+        let address = self.buf.as_ptr() as usize;
+        let offset = address.wrapping_neg() & (self.align - 1);
+        if offset != self.offset {
+            let len = self.len();
+            unsafe {
+                let buf = self.buf.as_mut_ptr();
+                ptr::copy(buf.add(self.offset), buf.add(offset), len);
+                self.buf.set_len(offset + len);
+            }
+            self.offset = offset;
         }
+    }
+}
 
-        unsafe {
-            let mut vec = Vec::with_capacity(type_size);
+/// Overwrites `len` bytes at `ptr` with zeros, in a way the compiler cannot elide.
+unsafe fn zero_bytes(ptr: *mut u8, len: usize) {
+    for i in 0..len {
+        ptr::write_volatile(ptr.add(i), 0);
+    }
+}
 
-            ptr::copy(
-                self.data.as_mut_ptr().offset(data_index as isize),
-                vec.as_mut_ptr(),
-                type_size,
-            );
-            ptr::copy(
-                self.data.as_mut_ptr().offset(
-                    (data_index + type_size) as isize,
-                ),
-                self.data.as_mut_ptr().offset(data_index as isize),
-                self.data.len() - (data_index + type_size),
-            );
-            let new_len = self.data.len() - type_size;
-            self.data.set_len(new_len);
+/// The metadata of the elements of an `AnyVecN`.
+#[cfg(not(feature = "smallvec"))]
+type MetaBuf<const N: usize> = Vec<AnyMeta>;
 
-            Ok(ptr::read(vec.as_ptr() as *const T))
-        }
+/// The metadata of the elements of an `AnyVecN`, inline for up to `N` elements.
+#[cfg(feature = "smallvec")]
+type MetaBuf<const N: usize> = smallvec::SmallVec<[AnyMeta; N]>;
 
-    }
+/// The fewest elements `par_map_in_place` hands to a thread, below which spawning one costs
+/// more than it saves.
+#[cfg(feature = "parallel")]
+const PAR_MIN_CHUNK: usize = 1024;
 
-    /// Removes and returns the element at position `index`.
-    ///
-    /// Shifts elements after position `index` to the left.
-    ///
-    /// # Panics
-    /// Panics if `index` is out of bounds.
-    pub fn remove(&mut self, index: usize) {
-        let type_size = self.meta[index].type_size;
-        let data_index = self.meta[index].data_index;
+/// Inserts `metas` at position `index`, moving the following entries only once.
+#[cfg(not(feature = "smallvec"))]
+fn insert_metas<I: IntoIterator<Item = AnyMeta>>(meta: &mut Vec<AnyMeta>, index: usize, metas: I) {
+    meta.splice(index..index, metas);
+}
 
-        self.meta.remove(index);
-        for i in index..self.meta.len() {
-            self.meta[i].data_index -= type_size;
-        }
+/// Inserts `metas` at position `index`, moving the following entries only once.
+#[cfg(feature = "smallvec")]
+fn insert_metas<const N: usize, I: IntoIterator<Item = AnyMeta>>(
+    meta: &mut MetaBuf<N>,
+    index: usize,
+    metas: I,
+) {
+    meta.insert_many(index, metas);
+}
 
-        unsafe {
-            ptr::copy(
-                self.data.as_mut_ptr().offset(
-                    (data_index + type_size) as isize,
-                ),
-                self.data.as_mut_ptr().offset(data_index as isize),
-                self.data.len() - (data_index + type_size),
-            );
-            let new_len = self.data.len() - type_size;
-            self.data.set_len(new_len);
-        }
+/// Tries to reserve capacity for at least `additional` more entries.
+#[cfg(not(feature = "smallvec"))]
+fn try_reserve_metas(meta: &mut Vec<AnyMeta>, additional: usize) -> Result<(), TryReserveError> {
+    meta.try_reserve(additional)
+}
+
+/// Tries to reserve capacity for at least `additional` more entries.
+///
+/// `SmallVec` has its own error type, and std only builds a `TryReserveError` from a failed
+/// reservation, so any failure is reported as a capacity overflow.
+#[cfg(feature = "smallvec")]
+fn try_reserve_metas<const N: usize>(
+    meta: &mut MetaBuf<N>,
+    additional: usize,
+) -> Result<(), TryReserveError> {
+    meta.try_reserve(additional).map_err(|_| capacity_overflow())
+}
+
+/// Returns the capacity overflow error of std, without allocating.
+fn capacity_overflow() -> TryReserveError {
+    // No `Vec<u8>` holds more than `isize::MAX` bytes, so this fails before allocating.
+    Vec::<u8>::new().try_reserve(usize::MAX).unwrap_err()
+}
+
+/// Rounds `n` up to a multiple of `align`, which must be a power of two.
+fn align_up(n: usize, align: usize) -> usize {
+    (n + align - 1) & !(align - 1)
+}
+
+/// Rounds `n` down to a multiple of `align`, which must be a power of two.
+fn align_down(n: usize, align: usize) -> usize {
+    n & !(align - 1)
+}
+
+/// Moves `data_index` from a block starting at `old_start` to one starting at `new_start`.
+///
+/// # Panics
+/// Panics if `data_index` lies before `old_start` or the result overflows `usize`.
+fn rebase(data_index: usize, old_start: usize, new_start: usize) -> usize {
+    data_index
+        .checked_sub(old_start)
+        .and_then(|offset| offset.checked_add(new_start))
+        .expect("data index out of range")
+}
+
+/// Moves an element out of the vector into a box.
+type BoxGlue = unsafe fn(*mut u8) -> Box<dyn Any>;
+
+/// Clones an element of the vector into a box.
+type CloneBoxGlue = unsafe fn(*const u8) -> Box<dyn Any>;
 
+/// A callback told the type of every element an `AnyVec` drops, set by `set_drop_observer`.
+struct DropObserver(Box<dyn FnMut(TypeId)>);
+
+impl fmt::Debug for DropObserver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("DropObserver")
     }
+}
 
-    /// Returns if element at position `index` is of type `T`,
-    /// or `None` if the index is out of bounds.
-    pub fn is<T: Any>(&self, index: usize) -> Option<bool> {
-        let meta = match self.meta.get(index) {
-            Some(meta) => meta,
-            None => return None,
-        };
-        Some(meta.type_id == TypeId::of::<T>())
+/// Runs the destructor of the element described by `meta`, stored at `ptr`, after telling
+/// `observer` its type.
+unsafe fn drop_observed(observer: &mut Option<DropObserver>, meta: &AnyMeta, ptr: *mut u8) {
+    if let Some(DropObserver(f)) = observer {
+        f(meta.type_id);
     }
+    meta.drop_element(ptr);
+}
 
-    /// Returns element at position `index` or `None` if the index is out of bounds.
-    pub fn get<T: Any>(&self, index: usize) -> Result<Option<&T>, String> {
-        let meta = match self.meta.get(index) {
-            Some(meta) => meta,
-            None => return Ok(None),
-        };
-        if meta.type_id != TypeId::of::<T>() {
-            Err(format!(
-                "invalid type {:?}, expected {:?}",
-                TypeId::of::<T>(),
-                meta.type_id
-            ))
-        } else {
-            unsafe {
-                Ok(Some(ptr::read(
-                    &&self.data[meta.data_index] as *const _ as *const &T,
-                )))
-            }
+#[derive(Debug, Clone, Copy)]
+struct AnyMeta {
+    data_index: usize,
+    type_id: TypeId,
+    type_size: usize,
+    type_align: usize,
+    drop_glue: unsafe fn(*mut u8),
+    box_glue: Option<BoxGlue>,
+    clone_glue: Option<unsafe fn(*const u8, *mut u8)>,
+    clone_box_glue: Option<CloneBoxGlue>,
+    cmp_glue: Option<unsafe fn(*const u8, *const u8) -> Ordering>,
+    /// The `TypeId` of a trait object type, and an erased `fn(&T) -> &Dyn` casting to it.
+    dyn_glue: Option<(TypeId, fn())>,
+    boxed: Option<Vtable>,
+    send: bool,
+    sync: bool,
+    handle: Option<Handle>,
+    type_name: Option<&'static str>,
+}
+
+impl AnyMeta {
+    /// Returns the metadata of a `T`, with `data_index` to be filled in on insertion.
+    fn of<T: Any>() -> Self {
+        AnyMeta {
+            data_index: 0,
+            type_id: TypeId::of::<T>(),
+            type_size: mem::size_of::<T>(),
+            type_align: mem::align_of::<T>(),
+            drop_glue: drop_glue::<T>,
+            box_glue: Some(box_glue::<T>),
+            clone_glue: None,
+            clone_box_glue: None,
+            cmp_glue: None,
+            dyn_glue: None,
+            boxed: None,
+            send: false,
+            sync: false,
+            handle: None,
+            type_name: None,
         }
     }
 
-    /// Returns mutable reference to element at position `index`,
-    /// or `None` if the index is out of bounds.
-    pub fn get_mut<T: Any>(&self, index: usize) -> Result<Option<&mut T>, String> {
-        let meta = match self.meta.get(index) {
-            Some(meta) => meta,
-            None => return Ok(None),
-        };
-        if meta.type_id != TypeId::of::<T>() {
-            Err(format!(
-                "invalid type {:?}, expected {:?}",
-                TypeId::of::<T>(),
-                meta.type_id
-            ))
+    /// Moves the element described by `self`, stored at `ptr`, into a box.
+    ///
+    /// Returns `None` if the type of the element is unknown.
+    unsafe fn box_element(&self, ptr: *mut u8) -> Option<Box<dyn Any>> {
+        if let Some(box_glue) = self.box_glue {
+            return Some(box_glue(ptr));
+        }
+        let vtable = self.boxed?;
+        let layout = Layout::from_size_align_unchecked(self.type_size, self.type_align);
+        let dst = if layout.size() == 0 {
+            ptr::without_provenance_mut(layout.align())
         } else {
-            unsafe {
-                Ok(Some(ptr::read(
-                    &&self.data[meta.data_index] as *const _ as *const &mut T,
-                )))
+            let dst = alloc::alloc(layout);
+            if dst.is_null() {
+                alloc::handle_alloc_error(layout);
             }
+            dst
+        };
+        ptr::copy_nonoverlapping(ptr, dst, self.type_size);
+        Some(Box::from_raw(vtable.with_data(dst)))
+    }
+
+    /// Runs the destructor of the element described by `self`, stored at `ptr`.
+    unsafe fn drop_element(&self, ptr: *mut u8) {
+        match self.boxed {
+            Some(vtable) => ptr::drop_in_place(vtable.with_data(ptr)),
+            None => (self.drop_glue)(ptr),
         }
     }
+}
 
-    /// Appends an element to the back of a collection.
-    ///
-    /// # Panics
-    /// Panics if the number of elements in the vector overflows a `usize`.
-    pub fn push<T: Any>(&mut self, value: T) {
-        let index = self.meta.len();
-        self.insert(index, value);
+/// A pointer only used for the vtable it carries, of an element moved out of a `Box<dyn Any>`.
+#[derive(Debug, Clone, Copy)]
+struct Vtable(*mut dyn Any);
+
+// The pointer is never dereferenced, only its vtable is used.
+unsafe impl Send for Vtable {}
+unsafe impl Sync for Vtable {}
+
+impl Vtable {
+    /// Returns a pointer to `data` with this vtable.
+    unsafe fn with_data(self, data: *mut u8) -> *mut dyn Any {
+        // The data pointer is the first half of a fat pointer, which the assertion checks.
+        let mut fat = self.0;
+        let data_ptr = &mut fat as *mut *mut dyn Any as *mut *mut u8;
+        assert_eq!(*data_ptr, self.0 as *mut u8);
+        *data_ptr = data;
+        fat
     }
+}
 
-    /// Returns the last element of the vector, or `None` if it is empty.
-    pub fn pop<T: Any>(&mut self) -> Result<Option<T>, String> {
-        if self.is_empty() {
-            Ok(None)
-        } else {
-            let index = self.meta.len() - 1;
-            match self.remove_and_return(index) {
-                Ok(element) => Ok(Some(element)),
-                Err(err) => Err(err),
-            }
-        }
+/// Fails if the element described by `meta` is not of the `requested` type.
+fn check_type(meta: &AnyMeta, requested: TypeId) -> Result<(), AnyVecError> {
+    if meta.type_id == requested {
+        Ok(())
+    } else {
+        Err(AnyVecError::TypeMismatch {
+            expected: requested,
+            found: meta.type_id,
+        })
     }
+}
 
-    /// Moves all the elements of `other` into `Self`, leaving `other` empty.
+/// Runs the destructor of the `T` stored at `ptr`.
+unsafe fn drop_glue<T>(ptr: *mut u8) {
+    ptr::drop_in_place(ptr as *mut T);
+}
+
+/// Clones the `T` stored at `ptr` into a box.
+unsafe fn clone_box_glue<T: Any + Clone>(ptr: *const u8) -> Box<dyn Any> {
+    Box::new((*(ptr as *const T)).clone())
+}
+
+/// Compares the `T`s stored at `a` and `b`.
+unsafe fn cmp_glue<T: Ord>(a: *const u8, b: *const u8) -> Ordering {
+    (*(a as *const T)).cmp(&*(b as *const T))
+}
+
+/// Moves the `T` stored at `ptr` into a box.
+unsafe fn box_glue<T: Any>(ptr: *mut u8) -> Box<dyn Any> {
+    Box::new(ptr::read(ptr as *const T))
+}
+
+/// Leaks the element stored at `ptr`, whose type is not known.
+unsafe fn forget_glue(_ptr: *mut u8) {}
+
+/// Writes a clone of the `T` stored at `src` to `dst`.
+unsafe fn clone_glue<T: Clone>(src: *const u8, dst: *mut u8) {
+    ptr::write(dst as *mut T, (*(src as *const T)).clone());
+}
+
+/// A growable list type with dynamic typing.
+///
+/// It can store anything that implements the `Any` trait.
+///
+/// Elements are stored back to back, each aligned as its type requires. Inserting or removing
+/// an element moves the following elements by multiples of their largest alignment, which may
+/// leave gaps of padding between elements; `compact` removes them.
+///
+/// `AnyVec` is neither `Send` nor `Sync`. Its elements are type-erased, so a blanket
+/// `unsafe impl` would let an `Rc` or a `Cell` be sent to or shared with another thread.
+/// Elements pushed via `push_send` or `push_sync` record their thread-safety instead,
+/// see `is_send`, `is_sync` and `into_sendable`.
+///
+/// `N` is the number of elements whose metadata is stored inline with the `smallvec`
+/// feature; without it, `N` has no effect. `AnyVec` picks the default of four.
+#[derive(Debug)]
+pub struct AnyVecN<const N: usize> {
+    data: AnyData,
+    meta: MetaBuf<N>,
+    locked_type: Option<TypeId>,
+    max_element_size: Option<usize>,
+    zeroize_on_remove: bool,
+    handles: HandleTable,
+    drop_observer: Option<DropObserver>,
+    _marker: PhantomData<*const ()>,
+}
+
+/// An `AnyVecN` storing the metadata of up to four elements inline.
+pub type AnyVec = AnyVecN<4>;
+
+/// A stable reference to an element pushed via `AnyVec::push_handle`.
+///
+/// Unlike an index, a handle keeps referring to the same element when other elements are
+/// inserted or removed, and stops resolving once that element is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: usize,
+    generation: u32,
+}
+
+/// The handle slots of an `AnyVec`.
+///
+/// A slot is freed once no element carries its handle anymore, and its generation is bumped
+/// so that the stale handle no longer resolves when the slot is reused.
+#[derive(Debug, Default)]
+struct HandleTable {
+    generations: Vec<u32>,
+    free: Vec<usize>,
+}
+
+/// An `AnyVec` whose elements are all `Send`, which makes it `Send` itself.
+///
+/// Created by `AnyVec::into_sendable`.
+#[derive(Debug)]
+pub struct SendAnyVec<const N: usize = 4> {
+    vec: AnyVecN<N>,
+}
+
+unsafe impl<const N: usize> Send for SendAnyVec<N> {}
+
+impl<const N: usize> SendAnyVec<N> {
+    /// Appends an element to the back of a collection.
     ///
     /// # Panics
     /// Panics if the number of elements in the vector overflows a `usize`.
-    pub fn append(&mut self, other: &mut AnyVec) {
-        let org_meta_size = self.meta.len();
-
-        self.meta.append(&mut other.meta);
-        for meta in self.meta.iter_mut().skip(org_meta_size) {
-            meta.data_index += self.data.len();
-        }
+    pub fn push<T: Any + Send>(&mut self, value: T) {
+        self.vec.push_send(value);
+    }
 
-        self.data.append(&mut other.data);
+    /// Returns the wrapped `AnyVec`.
+    pub fn into_inner(self) -> AnyVecN<N> {
+        self.vec
     }
+}
 
-    /// Clears the vector.
-    pub fn clear(&mut self) {
-        self.data.clear();
-        self.meta.clear();
+impl<const N: usize> Deref for SendAnyVec<N> {
+    type Target = AnyVecN<N>;
+
+    fn deref(&self) -> &AnyVecN<N> {
+        &self.vec
     }
+}
 
-    /// Returns the number of elements in the vector.
+/// A saved copy of the contents of an `AnyVec`.
+///
+/// Created by `AnyVec::snapshot` and handed back to `AnyVec::restore`. Every element is cloned
+/// through the clone glue recorded when it was pushed, so the copy is as deep as each type's
+/// `Clone` impl: `Copy` types are copied byte for byte, while e.g. a `String` gets its own
+/// buffer and an `Rc` only bumps its reference count.
+#[derive(Debug)]
+pub struct AnyVecSnapshot<const N: usize = 4> {
+    vec: AnyVecN<N>,
+}
+
+impl<const N: usize> AnyVecSnapshot<N> {
+    /// Returns the number of elements in the snapshot.
     pub fn len(&self) -> usize {
-        self.meta.len()
+        self.vec.len()
     }
 
-    /// Returns if the vector is empty.
+    /// Returns `true` if the snapshot contains no elements.
     pub fn is_empty(&self) -> bool {
-        self.meta.is_empty()
+        self.vec.is_empty()
     }
+}
 
-    /// Splits the collection into two at the given index.
-    ///
+/// A read-only `AnyVec`, compacted and shrunk to fit.
+///
+/// Created by `AnyVec::freeze`, and turned back into an `AnyVec` by `thaw`.
+#[derive(Debug)]
+pub struct FrozenAnyVec<const N: usize = 4> {
+    vec: AnyVecN<N>,
+}
+
+impl<const N: usize> FrozenAnyVec<N> {
+    /// Returns a reference to the element at position `index`, or `None` if the index is out
+    /// of bounds.
+    pub fn get<T: Any>(&self, index: usize) -> Result<Option<&T>, AnyVecError> {
+        self.vec.get(index)
+    }
+
+    /// Returns an iterator over the elements of type `T`, skipping elements of other types.
+    pub fn iter_type<T: Any>(&self) -> impl Iterator<Item = &T> + '_ {
+        self.vec.iter_type()
+    }
+
+    /// Returns the number of elements in the vector.
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    /// Returns if the vector is empty.
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    /// Returns the vector, mutable again.
+    pub fn thaw(self) -> AnyVecN<N> {
+        self.vec
+    }
+}
+
+/// A cursor over the elements of an `AnyVec`, which can remove the element it points at.
+///
+/// Created by `AnyVec::cursor_mut`, pointing at the first element.
+#[derive(Debug)]
+pub struct Cursor<'a, const N: usize = 4> {
+    vec: &'a mut AnyVecN<N>,
+    index: usize,
+}
+
+impl<'a, const N: usize> Cursor<'a, N> {
+    /// Returns the position of the element the cursor points at.
+    ///
+    /// Equals the length of the vector once the cursor moved past the last element.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Moves the cursor to the next element, returning if there is one.
+    pub fn move_next(&mut self) -> bool {
+        if self.index < self.vec.len() {
+            self.index += 1;
+        }
+        self.index < self.vec.len()
+    }
+
+    /// Returns a reference to the element the cursor points at, or `None` if the cursor moved
+    /// past the last element.
+    pub fn current<T: Any>(&self) -> Result<Option<&T>, AnyVecError> {
+        self.vec.get(self.index)
+    }
+
+    /// Returns a mutable reference to the element the cursor points at, or `None` if the
+    /// cursor moved past the last element.
+    pub fn current_mut<T: Any>(&mut self) -> Result<Option<&mut T>, AnyVecError> {
+        self.vec.get_mut(self.index)
+    }
+
+    /// Removes the element the cursor points at, moving the cursor to the element after it.
+    ///
     /// # Panics
-    /// Panics if `at > len`.
-    pub fn split_off(&mut self, at: usize) -> Self {
-        let other_data = self.data.split_off(self.meta[at].data_index);
-        let mut other_meta = self.meta.split_off(at);
+    /// Panics if the cursor moved past the last element.
+    pub fn remove(&mut self) {
+        self.vec.remove(self.index);
+    }
+}
+
+/// Plans the exact capacity of an `AnyVec` from the types it will store.
+///
+/// ```
+/// use anyvec::CapacityPlan;
+///
+/// let vec = CapacityPlan::new().add::<u32>(8).add::<u8>(4).build();
+/// assert_eq!(vec.capacity(1), 12);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct CapacityPlan {
+    bytes: usize,
+    elements: usize,
+    align: usize,
+}
+
+impl CapacityPlan {
+    /// Constructs a new, empty `CapacityPlan`.
+    pub fn new() -> Self {
+        CapacityPlan {
+            bytes: 0,
+            elements: 0,
+            align: 1,
+        }
+    }
+
+    /// Plans for `count` more elements of type `T`.
+    ///
+    /// The plan includes the alignment padding of pushing the elements in the planned order.
+    ///
+    /// # Panics
+    /// Panics if the planned capacity overflows `usize`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<T: Any>(mut self, count: usize) -> Self {
+        self.bytes = align_up(self.bytes, mem::align_of::<T>()) + count * mem::size_of::<T>();
+        self.elements += count;
+        self.align = cmp::max(self.align, mem::align_of::<T>());
+        self
+    }
+
+    /// Constructs a new, empty `AnyVec` with the planned capacity.
+    pub fn build(self) -> AnyVec {
+        self.build_inline()
+    }
+
+    /// Constructs a new, empty `AnyVecN` with the planned capacity, storing the metadata of
+    /// up to `N` elements inline.
+    pub fn build_inline<const N: usize>(self) -> AnyVecN<N> {
+        AnyVecN {
+            data: AnyData::with_capacity(self.bytes, self.align),
+            meta: MetaBuf::with_capacity(self.elements),
+            locked_type: None,
+            max_element_size: None,
+            zeroize_on_remove: false,
+            handles: HandleTable::default(),
+            drop_observer: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Default for CapacityPlan {
+    fn default() -> Self {
+        CapacityPlan::new()
+    }
+}
+
+impl<const N: usize> Default for AnyVecN<N> {
+    fn default() -> Self {
+        AnyVecN::new()
+    }
+}
+
+impl<T: Any, const N: usize> Extend<T> for AnyVecN<N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve_for::<T>(iter.size_hint().0);
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<const N: usize> AnyVecN<N> {
+    /// Constructs a new, empty `AnyVec`.
+    pub fn new() -> Self {
+        Self {
+            data: AnyData::new(),
+            meta: MetaBuf::new(),
+            locked_type: None,
+            max_element_size: None,
+            zeroize_on_remove: false,
+            handles: HandleTable::default(),
+            drop_observer: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Constructs a new, empty `AnyVec` with specified capacity.
+    ///
+    /// Since we do not type sizes ahead, an average type size `avg_type_size` must be specified.
+    pub fn with_capacity(capacity: usize, avg_type_size: usize) -> Self {
+        Self {
+            data: AnyData::with_capacity(capacity * avg_type_size, 1),
+            meta: MetaBuf::with_capacity(capacity),
+            locked_type: None,
+            max_element_size: None,
+            zeroize_on_remove: false,
+            handles: HandleTable::default(),
+            drop_observer: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Constructs a new `AnyVec` holding `count` default values of type `T`.
+    pub fn from_default<T: Any + Default>(count: usize) -> Self {
+        let mut vec = CapacityPlan::new().add::<T>(count).build_inline();
+        for _ in 0..count {
+            vec.push(T::default());
+        }
+        vec
+    }
+
+    /// Constructs a new `AnyVec` holding copies of the elements of `values`.
+    ///
+    /// Copies all the values at once, like `insert_slice`.
+    pub fn from_slice<T: Any + Copy>(values: &[T]) -> Self {
+        let mut vec = CapacityPlan::new().add::<T>(values.len()).build_inline();
+        vec.insert_slice(0, values);
+        vec
+    }
+
+    /// Constructs an `AnyVec` from raw bytes, and the type, data index and size of each
+    /// element in `meta`.
+    ///
+    /// Each element is taken to be aligned to the largest power of two dividing both its
+    /// index and size. The bytes are moved if `data` lacks the room to align them. The
+    /// elements are never dropped.
+    ///
+    /// # Safety
+    /// Each entry of `meta` must describe a valid value of the type with that `TypeId`,
+    /// stored at its index in `data`. The entries must be in ascending order of index with no
+    /// overlapping ranges, and each index must be a multiple of the alignment of its type.
+    pub unsafe fn from_raw_parts(data: Vec<u8>, meta: Vec<(TypeId, usize, usize)>) -> Self {
+        let mut vec = Self {
+            data: AnyData::from_vec(data),
+            meta: MetaBuf::with_capacity(meta.len()),
+            locked_type: None,
+            max_element_size: None,
+            zeroize_on_remove: false,
+            handles: HandleTable::default(),
+            drop_observer: None,
+            _marker: PhantomData,
+        };
+        for (type_id, data_index, type_size) in meta {
+            debug_assert!(
+                matches!(data_index.checked_add(type_size), Some(end) if end <= vec.data.len()),
+                "element at byte {} out of bounds",
+                data_index
+            );
+            let bits = data_index | type_size;
+            let type_align = if bits == 0 { 1 } else { 1 << bits.trailing_zeros() };
+            vec.meta.push(AnyMeta {
+                data_index,
+                type_id,
+                type_size,
+                type_align,
+                drop_glue: forget_glue,
+                box_glue: None,
+                clone_glue: None,
+                clone_box_glue: None,
+                cmp_glue: None,
+                dyn_glue: None,
+                boxed: None,
+                send: false,
+                sync: false,
+                handle: None,
+                type_name: None,
+            });
+            vec.data.align_to(type_align);
+        }
+        let data_len = vec.data_end(vec.meta.len());
+        vec.data.set_len(data_len);
+        vec
+    }
+
+    /// Constructs an `AnyVec` from boxed values, moving each value out of its box.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn from_boxed_iter<I: IntoIterator<Item = Box<dyn Any>>>(iter: I) -> Self {
+        let mut vec = Self::new();
+        for value in iter {
+            vec.push_box(value);
+        }
+        vec
+    }
+
+    /// Concatenates the vectors in `parts` into one, in order.
+    ///
+    /// Allocates the capacity once, including the alignment padding between the parts.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn concat(parts: Vec<Self>) -> Self {
+        let align = parts.iter().map(|part| part.data.align()).max().unwrap_or(1);
+        let bytes = parts
+            .iter()
+            .map(|part| part.data.len() + part.data.align() - 1)
+            .sum();
+        let mut vec = Self {
+            data: AnyData::with_capacity(bytes, align),
+            meta: MetaBuf::with_capacity(parts.iter().map(|part| part.len()).sum()),
+            locked_type: None,
+            max_element_size: None,
+            zeroize_on_remove: false,
+            handles: HandleTable::default(),
+            drop_observer: None,
+            _marker: PhantomData,
+        };
+        for mut part in parts {
+            vec.append(&mut part);
+        }
+        vec
+    }
+
+    /// Constructs an `AnyVec` interleaving the elements of `a`, `b` and `c` in row order,
+    /// `[a[0], b[0], c[0], a[1], ...]`.
+    ///
+    /// Returns an error if the lengths of `b` or `c` differ from the length of `a`.
+    pub fn interleave3<A: Any, B: Any, C: Any>(
+        a: Vec<A>,
+        b: Vec<B>,
+        c: Vec<C>,
+    ) -> Result<Self, AnyVecError> {
+        let len = a.len();
+        for &found in &[b.len(), c.len()] {
+            if found != len {
+                return Err(AnyVecError::LengthMismatch {
+                    expected: len,
+                    found,
+                });
+            }
+        }
+
+        let mut vec = CapacityPlan::new()
+            .add::<A>(len)
+            .add::<B>(len)
+            .add::<C>(len)
+            .build_inline();
+        for ((a, b), c) in a.into_iter().zip(b).zip(c) {
+            vec.push(a);
+            vec.push(b);
+            vec.push(c);
+        }
+        Ok(vec)
+    }
+
+    /// Returns the number of elements of size `type_size` the vector can hold without
+    /// reallocating.
+    ///
+    /// Elements of size 0 take no data, so only the metadata capacity limits them.
+    pub fn capacity(&self, type_size: usize) -> usize {
+        match self.data.capacity().checked_div(type_size) {
+            Some(capacity) => cmp::min(self.meta.capacity(), capacity),
+            None => self.meta.capacity(),
+        }
+    }
+
+    /// Returns the number of elements the metadata can hold without reallocating, whatever
+    /// their size.
+    pub fn capacity_elements(&self) -> usize {
+        self.meta.capacity()
+    }
+
+    /// Reserves capacity for at least `additional` more elements.
+    ///
+    /// Since we do not type sizes ahead, an average type size `avg_type_size` must be specified.
+    ///
+    /// # Panics
+    /// Panics if the new capacity overflows `usize`.
+    pub fn reserve(&mut self, additional: usize, avg_type_size: usize) {
+        self.data.reserve(additional * avg_type_size);
+        self.meta.reserve(additional);
+    }
+
+    /// Reserves capacity for exactly `additional` more elements.
+    ///
+    /// Since we do not type sizes ahead, an average type size `avg_type_size` must be specified.
+    ///
+    /// # Panics
+    /// Panics if the new capacity overflows `usize`.
+    pub fn reserve_exact(&mut self, additional: usize, avg_type_size: usize) {
+        self.data.reserve_exact(additional * avg_type_size);
+        self.meta.reserve_exact(additional);
+    }
+
+    /// Reserves capacity for at least `bytes` more bytes of element data.
+    ///
+    /// # Panics
+    /// Panics if the new capacity overflows `usize`.
+    pub fn reserve_data(&mut self, bytes: usize) {
+        self.data.reserve(bytes);
+    }
+
+    /// Reserves capacity for the metadata of at least `elements` more elements.
+    ///
+    /// # Panics
+    /// Panics if the new capacity overflows `usize`.
+    pub fn reserve_meta(&mut self, elements: usize) {
+        self.meta.reserve(elements);
+    }
+
+    /// Reserves capacity for at least `additional` more elements of type `T`.
+    ///
+    /// Includes the alignment padding before the first of them.
+    ///
+    /// # Panics
+    /// Panics if the new capacity overflows `usize`.
+    pub fn reserve_for<T: Any>(&mut self, additional: usize) {
+        let align = mem::align_of::<T>();
+        self.data.align_to(align);
+        self.data.reserve(additional * mem::size_of::<T>() + align - 1);
+        self.meta.reserve(additional);
+    }
+
+    /// Raises the alignment of the buffer to that of `T`, and reserves room for an element of
+    /// type `T` with the padding before it.
+    ///
+    /// Afterwards, pushing an element of type `T` neither realigns nor reallocates the buffer.
+    ///
+    /// # Panics
+    /// Panics if the new capacity overflows `usize`.
+    pub fn align_for<T: Any>(&mut self) {
+        let align = mem::align_of::<T>();
+        let padding = align_up(self.data.len(), align) - self.data.len();
+        self.data.reserve_aligned(align, padding + mem::size_of::<T>());
+    }
+
+    /// Reserves capacity for appending all the elements of `other`, so that a following
+    /// `append` does not reallocate.
+    ///
+    /// Includes the alignment padding before the appended elements. Reserves nothing if
+    /// `other` is empty.
+    ///
+    /// # Panics
+    /// Panics if the new capacity overflows `usize`.
+    pub fn reserve_for_append(&mut self, other: &Self) {
+        if other.is_empty() {
+            return;
+        }
+        let align = other.data.align();
+        let padding = align_up(self.data.len(), align) - self.data.len();
+        self.data.reserve_aligned(align, padding + other.data_len());
+        self.meta.reserve(other.len());
+    }
+
+    /// Tries to reserve capacity for at least `additional` more elements.
+    ///
+    /// Since we do not type sizes ahead, an average type size `avg_type_size` must be specified.
+    ///
+    /// Returns an error instead of panicking if the new capacity overflows `usize`,
+    /// or if the allocator reports a failure.
+    pub fn try_reserve(
+        &mut self,
+        additional: usize,
+        avg_type_size: usize,
+    ) -> Result<(), TryReserveError> {
+        let bytes = additional
+            .checked_mul(avg_type_size)
+            .ok_or_else(capacity_overflow)?;
+        self.data.try_reserve(bytes)?;
+        try_reserve_metas(&mut self.meta, additional)
+    }
+
+    /// Sets how the data buffer grows when inserting elements or reserving capacity.
+    ///
+    /// `Growth::Exact` keeps the buffer tight, at the cost of reallocating on every insertion.
+    pub fn set_growth(&mut self, strategy: Growth) {
+        self.data.growth = strategy;
+    }
+
+    /// Shrinks the capacity of the vector as much as possible.
+    ///
+    /// Afterwards, the capacity for data equals `data_len` exactly, so all elements of the
+    /// vector's element types fit, even if their sizes do not divide the capacity evenly.
+    pub fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit();
+        self.meta.shrink_to_fit();
+    }
+
+    /// Resizes the vector to be `new_len` elements long, like `Vec::resize`.
+    ///
+    /// Grows by pushing clones of `value`, as cloneable elements of type `T` whatever the
+    /// types of the elements already in the vector, or shrinks by dropping the elements after
+    /// position `new_len`.
+    ///
+    /// # Panics
+    /// Panics if the new capacity overflows `usize`, or if the vector is locked to another
+    /// type and has to grow.
+    pub fn resize<T: Any + Clone>(&mut self, new_len: usize, value: T) {
+        let len = self.meta.len();
+        if new_len <= len {
+            self.truncate(new_len);
+            return;
+        }
+
+        self.reserve_for::<T>(new_len - len);
+        for _ in len + 1..new_len {
+            self.push_cloneable(value.clone());
+        }
+        self.push_cloneable(value);
+    }
+
+    /// Shortens the vector to be `len` elements long.
+    ///
+    /// Drops the elements after position `len`.
+    pub fn truncate(&mut self, len: usize) {
+        self.truncate_counted(len);
+    }
+
+    /// Shortens the vector to be `len` elements long, returning the number of elements dropped.
+    pub fn truncate_counted(&mut self, len: usize) -> usize {
+        if len >= self.meta.len() {
+            return 0;
+        }
+        let count = self.meta.len() - len;
+        let data_len = self.data_end(len);
+        let old_data_len = self.data.len();
+
+        unsafe {
+            // Shorten first, so a panicking destructor leaks the rest instead of dropping twice.
+            self.data.set_len(data_len);
+            let data = self.data.as_mut_ptr();
+            for meta in self.meta.drain(len..) {
+                drop_observed(&mut self.drop_observer, &meta, data.add(meta.data_index));
+            }
+        }
+        self.zeroize(data_len..old_data_len);
+        count
+    }
+
+    /// Inserts an element at position `index` in the vector.
+    ///
+    /// Shifts elements after position `index` to the right.
+    ///
+    /// # Panics
+    /// Panics if `index` is greater than the vector's length, or if the vector is locked to
+    /// another type.
+    pub fn insert<T: Any>(&mut self, index: usize, element: T) {
+        self.insert_meta(index, element, AnyMeta::of::<T>());
+    }
+
+    /// Inserts an element at position `index`, or returns an error if the vector is locked
+    /// to another type or the element exceeds its maximum element size.
+    ///
+    /// # Panics
+    /// Panics if `index` is greater than the vector's length.
+    pub fn try_insert<T: Any>(&mut self, index: usize, element: T) -> Result<(), AnyVecError> {
+        self.check_insertable(TypeId::of::<T>(), mem::size_of::<T>())?;
+        self.insert(index, element);
+        Ok(())
+    }
+
+    /// Inserts a default value of type `T` at position `index` in the vector.
+    ///
+    /// Shifts elements after position `index` to the right.
+    ///
+    /// # Panics
+    /// Panics if `index` is greater than the vector's length, or if the vector is locked to
+    /// another type.
+    pub fn insert_default<T: Any + Default>(&mut self, index: usize) {
+        self.insert(index, T::default());
+    }
+
+    /// Locks the vector to elements of type `T`.
+    ///
+    /// Until `unlock_type` is called, pushing or inserting any other type fails: `try_push`
+    /// and `try_insert` return an error, the other methods panic. Elements already in the
+    /// vector are not checked.
+    pub fn lock_type<T: Any>(&mut self) {
+        self.locked_type = Some(TypeId::of::<T>());
+    }
+
+    /// Allows elements of any type again, after `lock_type`.
+    pub fn unlock_type(&mut self) {
+        self.locked_type = None;
+    }
+
+    /// Limits the size of elements to `bytes`.
+    ///
+    /// From then on, pushing or inserting a larger element fails: `try_push` and `try_insert`
+    /// return an error, the other methods panic. Elements already in the vector are not
+    /// checked.
+    pub fn set_max_element_size(&mut self, bytes: usize) {
+        self.max_element_size = Some(bytes);
+    }
+
+    /// Sets whether to overwrite the bytes of elements with zeros once they are removed,
+    /// truncated or dropped with the vector.
+    ///
+    /// This also zeros the bytes left behind when removals move the following elements, or
+    /// when elements move to another vector, as in `split_off` or `append`.
+    /// Bytes left behind when the vector reallocates are not zeroed, so reserve enough
+    /// capacity up front when storing secrets.
+    pub fn set_zeroize(&mut self, zeroize: bool) {
+        self.zeroize_on_remove = zeroize;
+    }
+
+    /// Inserts an element at position `index`, described by `meta`.
+    fn insert_meta<T: Any>(&mut self, index: usize, element: T, mut meta: AnyMeta) {
+        assert!(
+            index <= self.meta.len(),
+            "insertion index (is {}) should be <= len (is {})",
+            index,
+            self.meta.len()
+        );
+        if let Err(err) = self.check_insertable(TypeId::of::<T>(), mem::size_of::<T>()) {
+            panic!("{}", err);
+        }
+
+        let data_index = self.make_room(index, meta.type_size, meta.type_align);
+        unsafe {
+            ptr::write(self.data.as_mut_ptr().add(data_index) as *mut T, element);
+        }
+        meta.data_index = data_index;
+        self.meta.insert(index, meta);
+    }
+
+    /// Inserts all elements of `values` at position `index` in the vector.
+    ///
+    /// Shifts elements after position `index` to the right, once for the whole slice.
+    ///
+    /// # Panics
+    /// Panics if `index` is greater than the vector's length.
+    pub fn insert_slice<T: Any + Copy>(&mut self, index: usize, values: &[T]) {
+        assert!(
+            index <= self.meta.len(),
+            "insertion index (is {}) should be <= len (is {})",
+            index,
+            self.meta.len()
+        );
+        if let Err(err) = self.check_insertable(TypeId::of::<T>(), mem::size_of::<T>()) {
+            panic!("{}", err);
+        }
+        let type_size = mem::size_of::<T>();
+        let bytes = mem::size_of_val(values);
+
+        let data_index = self.make_room(index, bytes, mem::align_of::<T>());
+
+        unsafe {
+            ptr::copy_nonoverlapping(
+                values.as_ptr() as *const u8,
+                self.data.as_mut_ptr().add(data_index),
+                bytes,
+            );
+        }
+        insert_metas(
+            &mut self.meta,
+            index,
+            (0..values.len()).map(|i| {
+                let mut meta = AnyMeta::of::<T>();
+                meta.data_index = data_index + i * type_size;
+                meta
+            }),
+        );
+    }
+
+    /// Inserts an element of type `type_id` at position `index`, copied from `bytes`.
+    ///
+    /// Shifts elements after position `index` to the right. The element is dropped by running
+    /// `drop_glue` on a pointer to it, or never if `drop_glue` is `None`. It cannot be boxed,
+    /// cloned or compared.
+    ///
+    /// # Safety
+    /// `bytes` must hold a valid value of the type `T` with `TypeId` `type_id`, just like
+    /// `mem::transmute`: `bytes.len()` must be `size_of::<T>()`, `align` must be
+    /// `align_of::<T>()`, and `drop_glue` must drop a `T` in place. The value is moved into
+    /// the vector, so it must not be used or dropped anywhere else.
+    ///
+    /// # Panics
+    /// Panics if `index` is greater than the vector's length, if `align` is not a power of
+    /// two, or if the vector is locked to another type.
+    pub unsafe fn insert_bytes(
+        &mut self,
+        index: usize,
+        type_id: TypeId,
+        bytes: &[u8],
+        align: usize,
+        drop_glue: Option<unsafe fn(*mut u8)>,
+    ) {
+        assert!(
+            index <= self.meta.len(),
+            "insertion index (is {}) should be <= len (is {})",
+            index,
+            self.meta.len()
+        );
+        assert!(align.is_power_of_two(), "alignment (is {}) should be a power of two", align);
+        if let Err(err) = self.check_insertable(type_id, bytes.len()) {
+            panic!("{}", err);
+        }
+
+        let data_index = self.make_room(index, bytes.len(), align);
+
+        ptr::copy_nonoverlapping(
+            bytes.as_ptr(),
+            self.data.as_mut_ptr().add(data_index),
+            bytes.len(),
+        );
+        self.meta.insert(
+            index,
+            AnyMeta {
+                data_index,
+                type_id,
+                type_size: bytes.len(),
+                type_align: align,
+                drop_glue: drop_glue.unwrap_or(forget_glue),
+                box_glue: None,
+                clone_glue: None,
+                clone_box_glue: None,
+                cmp_glue: None,
+                dyn_glue: None,
+                boxed: None,
+                send: false,
+                sync: false,
+                handle: None,
+                type_name: None,
+            },
+        );
+    }
+
+    /// Removes and returns the element at position `index`.
+    ///
+    /// Shifts elements after position `index` to the left.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn remove_and_return<T: Any>(&mut self, index: usize) -> Result<T, AnyVecError> {
+        check_type(&self.meta[index], TypeId::of::<T>())?;
+
+        let mut element = None;
+        unsafe {
+            self.remove_with(index, |_, ptr| element = Some(ptr::read(ptr as *const T)));
+        }
+        Ok(element.unwrap())
+    }
+
+    /// Removes and drops the element at position `index`.
+    ///
+    /// Shifts elements after position `index` to the left.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) {
+        let mut observer = self.drop_observer.take();
+        unsafe {
+            self.remove_with(index, |meta, ptr| drop_observed(&mut observer, meta, ptr));
+        }
+        self.drop_observer = observer;
+    }
+
+    /// Returns if element at position `index` is of type `T`,
+    /// or `None` if the index is out of bounds.
+    pub fn is<T: Any>(&self, index: usize) -> Option<bool> {
+        let meta = self.meta.get(index)?;
+        Some(meta.type_id == TypeId::of::<T>())
+    }
+
+    /// Returns the alignment of the element at position `index`,
+    /// or `None` if the index is out of bounds.
+    ///
+    /// For elements from `from_raw_parts`, this is the alignment inferred there.
+    pub fn align_at(&self, index: usize) -> Option<usize> {
+        self.meta.get(index).map(|meta| meta.type_align)
+    }
+
+    /// Returns if the element at position `index` is stored at an address aligned for its
+    /// type, or `None` if the index is out of bounds.
+    ///
+    /// The vector aligns every element it stores, so this only fails for a broken invariant.
+    pub fn is_aligned_at(&self, index: usize) -> Option<bool> {
+        let meta = self.meta.get(index)?;
+        let address = self.data.as_ptr() as usize + meta.data_index;
+        Some(address.is_multiple_of(meta.type_align))
+    }
+
+    /// Removes the element at position `index` and returns it boxed, replacing it with the
+    /// last element.
+    ///
+    /// Returns `None` if the index is out of bounds, or for an element from `from_raw_parts` or
+    /// `insert_bytes`, whose type is unknown, leaving the vector unchanged.
+    pub fn swap_remove_any(&mut self, index: usize) -> Option<Box<dyn Any>> {
+        let meta = *self.meta.get(index)?;
+        let element = unsafe { meta.box_element(self.data.as_mut_ptr().add(meta.data_index))? };
+        self.zeroize(meta.data_index..meta.data_index + meta.type_size);
+        let old_data_len = self.data.len();
+
+        let mut last = self.meta.pop().unwrap();
+        let data_end = self.data_end(self.meta.len());
+        if index < self.meta.len() {
+            unsafe {
+                let mut bytes = Vec::<u8>::with_capacity(last.type_size);
+                ptr::copy_nonoverlapping(
+                    self.data.as_ptr().add(last.data_index),
+                    bytes.as_mut_ptr(),
+                    last.type_size,
+                );
+                self.data.set_len(data_end);
+
+                let data_index = align_up(self.data_end(index), last.type_align);
+                self.move_tail(index + 1, data_index + last.type_size);
+                ptr::copy_nonoverlapping(
+                    bytes.as_ptr(),
+                    self.data.as_mut_ptr().add(data_index),
+                    last.type_size,
+                );
+                if self.zeroize_on_remove {
+                    zero_bytes(bytes.as_mut_ptr(), last.type_size);
+                }
+                last.data_index = data_index;
+            }
+            self.meta[index] = last;
+        } else {
+            unsafe { self.data.set_len(data_end) };
+        }
+        self.zeroize(self.data.len()..old_data_len);
+        Some(element)
+    }
+
+    /// Returns element at position `index` or `None` if the index is out of bounds.
+    pub fn get<T: Any>(&self, index: usize) -> Result<Option<&T>, AnyVecError> {
+        let meta = match self.meta.get(index) {
+            Some(meta) => meta,
+            None => return Ok(None),
+        };
+        check_type(meta, TypeId::of::<T>())?;
+        unsafe { Ok(Some(self.element_ref(meta))) }
+    }
+
+    /// Returns a copy of the element at position `index`, or `None` if the index is out of
+    /// bounds.
+    pub fn get_copy<T: Any + Copy>(&self, index: usize) -> Result<Option<T>, AnyVecError> {
+        Ok(self.get::<T>(index)?.copied())
+    }
+
+    /// Returns mutable reference to element at position `index`,
+    /// or `None` if the index is out of bounds.
+    pub fn get_mut<T: Any>(&mut self, index: usize) -> Result<Option<&mut T>, AnyVecError> {
+        let meta = match self.meta.get(index) {
+            Some(meta) => meta,
+            None => return Ok(None),
+        };
+        check_type(meta, TypeId::of::<T>())?;
+        let data_index = meta.data_index;
+        unsafe { Ok(Some(&mut *(self.data.as_mut_ptr().add(data_index) as *mut T))) }
+    }
+
+    /// Returns a mutable reference to the element at position `index`.
+    ///
+    /// Fails if the index is out of bounds or if the element is not of type `T`.
+    pub fn get_mut_checked<T: Any>(&mut self, index: usize) -> Result<&mut T, AnyVecError> {
+        let data_index = self.checked_meta::<T>(index)?.data_index;
+        unsafe { Ok(&mut *(self.data.as_mut_ptr().add(data_index) as *mut T)) }
+    }
+
+    /// Returns the elements before, at and after position `index`, with `None` at the
+    /// boundaries.
+    ///
+    /// Fails if the index is out of bounds or if any of the three elements is not of type `T`.
+    pub fn neighbors<T: Any>(
+        &self,
+        index: usize,
+    ) -> Result<(Option<&T>, &T, Option<&T>), AnyVecError> {
+        let current = self.checked_meta::<T>(index)?;
+        let prev = match index.checked_sub(1) {
+            Some(prev) => Some(self.checked_meta::<T>(prev)?),
+            None => None,
+        };
+        let next = match self.meta.get(index + 1) {
+            Some(_) => Some(self.checked_meta::<T>(index + 1)?),
+            None => None,
+        };
+        unsafe {
+            Ok((
+                prev.map(|meta| self.element_ref(meta)),
+                self.element_ref(current),
+                next.map(|meta| self.element_ref(meta)),
+            ))
+        }
+    }
+
+    /// Reinterprets the bytes of the `From` element at position `index` as a `To`, in place.
+    ///
+    /// The element is dropped as a `To` from then on, and cannot be cloned or compared
+    /// anymore. Fails if the index is out of bounds, if the element is not of type `From`, if
+    /// `To` has a different size or if the element is not aligned for `To`. The vector is left
+    /// unchanged on error.
+    ///
+    /// # Safety
+    /// This is as dangerous as `mem::transmute::<From, To>`: the bytes of the element must be
+    /// a valid `To`, and it must be fine to never drop the `From`.
+    pub unsafe fn transmute_element<From: Any, To: Any>(
+        &mut self,
+        index: usize,
+    ) -> Result<(), AnyVecError> {
+        let meta = *self.checked_meta::<From>(index)?;
+        if mem::size_of::<To>() != mem::size_of::<From>() {
+            return Err(AnyVecError::SizeMismatch {
+                expected: mem::size_of::<From>(),
+                found: mem::size_of::<To>(),
+            });
+        }
+        let align = mem::align_of::<To>();
+        if align > self.data.align() || meta.data_index % align != 0 {
+            return Err(AnyVecError::Misaligned { index, align });
+        }
+        self.check_insertable(TypeId::of::<To>(), mem::size_of::<To>())?;
+
+        self.meta[index] = AnyMeta {
+            data_index: meta.data_index,
+            handle: meta.handle,
+            ..AnyMeta::of::<To>()
+        };
+        Ok(())
+    }
+
+    /// Returns a pointer to the element at position `index` and its size in bytes,
+    /// or `None` if the index is out of bounds.
+    ///
+    /// The pointer is aligned for the element's type, but only valid until the vector is
+    /// mutated or dropped, and must not be written through.
+    pub fn element_ptr(&self, index: usize) -> Option<(*const u8, usize)> {
+        let meta = self.meta.get(index)?;
+        Some((
+            unsafe { self.data.as_ptr().add(meta.data_index) },
+            meta.type_size,
+        ))
+    }
+
+    /// Returns a mutable pointer to the element at position `index` and its size in bytes,
+    /// or `None` if the index is out of bounds.
+    ///
+    /// The pointer is aligned for the element's type, but only valid until the vector is
+    /// otherwise accessed, mutated or dropped. Writes through it must leave a valid value of
+    /// the element's type behind.
+    pub fn element_ptr_mut(&mut self, index: usize) -> Option<(*mut u8, usize)> {
+        let meta = self.meta.get(index)?;
+        Some((
+            unsafe { self.data.as_mut_ptr().add(meta.data_index) },
+            meta.type_size,
+        ))
+    }
+
+    /// Returns mutable references to the elements at positions `i` and `j`.
+    ///
+    /// Fails if `i == j`, if either index is out of bounds or if an element is not of the
+    /// requested type.
+    pub fn get_two_mut<A: Any, B: Any>(
+        &mut self,
+        i: usize,
+        j: usize,
+    ) -> Result<(&mut A, &mut B), AnyVecError> {
+        if i == j {
+            return Err(AnyVecError::DuplicateIndex(i));
+        }
+        let data_index_a = self.checked_meta::<A>(i)?.data_index;
+        let data_index_b = self.checked_meta::<B>(j)?.data_index;
+
+        unsafe {
+            let data = self.data.as_mut_ptr();
+            Ok((
+                &mut *(data.add(data_index_a) as *mut A),
+                &mut *(data.add(data_index_b) as *mut B),
+            ))
+        }
+    }
+
+    /// Returns a reference to the element at position `read` and a mutable reference to the
+    /// element at position `write`.
+    ///
+    /// Fails if `read == write`, if either index is out of bounds or if an element is not of
+    /// the requested type.
+    pub fn get_ref_and_mut<A: Any, B: Any>(
+        &mut self,
+        read: usize,
+        write: usize,
+    ) -> Result<(&A, &mut B), AnyVecError> {
+        let (a, b) = self.get_two_mut::<A, B>(read, write)?;
+        Ok((a, b))
+    }
+
+    /// Returns mutable references to the elements at the positions in `indices`.
+    ///
+    /// Fails if an index repeats, is out of bounds or if its element is not of type `T`.
+    pub fn get_disjoint_mut<T: Any, const M: usize>(
+        &mut self,
+        indices: [usize; M],
+    ) -> Result<[&mut T; M], AnyVecError> {
+        for (i, &index) in indices.iter().enumerate() {
+            if indices[..i].contains(&index) {
+                return Err(AnyVecError::DuplicateIndex(index));
+            }
+            self.checked_meta::<T>(index)?;
+        }
+
+        let data = self.data.as_mut_ptr();
+        let meta = &self.meta;
+        Ok(indices.map(|index| unsafe { &mut *(data.add(meta[index].data_index) as *mut T) }))
+    }
+
+    /// Applies `f` to every element of type `T`, skipping elements of other types.
+    pub fn map_in_place<T: Any, F: FnMut(&mut T)>(&mut self, mut f: F) {
+        let type_id = TypeId::of::<T>();
+        let data = self.data.as_mut_ptr();
+        for meta in &self.meta {
+            if meta.type_id == type_id {
+                f(unsafe { &mut *(data.add(meta.data_index) as *mut T) });
+            }
+        }
+    }
+
+    /// Applies `f` to every element of type `T` in parallel, skipping elements of other types.
+    ///
+    /// The elements are split evenly across scoped threads, at most one per available core
+    /// and at least 1024 elements each, so small inputs run on the calling thread.
+    /// This uses `std::thread::scope` rather than rayon, to keep the crate free of
+    /// dependencies beyond the optional `smallvec`.
+    ///
+    /// # Panics
+    /// Panics if `f` panics, after all threads finished.
+    #[cfg(feature = "parallel")]
+    pub fn par_map_in_place<T: Any + Send, F: Fn(&mut T) + Sync>(&mut self, f: F) {
+        let type_id = TypeId::of::<T>();
+        let data = self.data.as_mut_ptr();
+        let mut elements: Vec<&mut T> = self
+            .meta
+            .iter()
+            .filter(|meta| meta.type_id == type_id)
+            .map(|meta| unsafe { &mut *(data.add(meta.data_index) as *mut T) })
+            .collect();
+        let cores = thread::available_parallelism().map_or(1, |n| n.get());
+        let threads = cmp::min(cores, elements.len() / PAR_MIN_CHUNK);
+        if threads <= 1 {
+            for element in elements {
+                f(element);
+            }
+            return;
+        }
+
+        let chunk_len = elements.len().div_ceil(threads);
+        let f = &f;
+        thread::scope(|scope| {
+            for chunk in elements.chunks_mut(chunk_len) {
+                scope.spawn(move || {
+                    for element in chunk {
+                        f(element);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Returns an iterator over the elements of type `T` as cells, skipping elements of other
+    /// types.
+    ///
+    /// `Cell<T>` has the same in-memory representation as `T`, so each element is viewed in
+    /// place. This borrows the vector mutably, as the elements can be written through the
+    /// shared cells; the cells can be held at the same time, like `Cell::as_slice_of_cells`.
+    pub fn iter_cells<T: Any>(&mut self) -> impl Iterator<Item = &Cell<T>> + '_ {
+        let type_id = TypeId::of::<T>();
+        let data = self.data.as_mut_ptr();
+        self.meta
+            .iter()
+            .filter(move |meta| meta.type_id == type_id)
+            .map(move |meta| unsafe { Cell::from_mut(&mut *(data.add(meta.data_index) as *mut T)) })
+    }
+
+    /// Returns an iterator over the elements of type `T`, skipping elements of other types.
+    pub fn iter_type<T: Any>(&self) -> impl Iterator<Item = &T> + '_ {
+        let type_id = TypeId::of::<T>();
+        self.meta
+            .iter()
+            .filter(move |meta| meta.type_id == type_id)
+            .map(move |meta| unsafe { self.element_ref(meta) })
+    }
+
+    /// Returns an iterator over the elements at the positions in `order`, in that order.
+    ///
+    /// Skips the positions that are out of bounds or hold an element of another type.
+    pub fn iter_type_by<'a, T: Any>(&'a self, order: &'a [usize]) -> impl Iterator<Item = &'a T> {
+        order
+            .iter()
+            .filter_map(move |&index| self.checked_meta::<T>(index).ok())
+            .map(move |meta| unsafe { self.element_ref(meta) })
+    }
+
+    /// Returns an iterator over the elements of type `L` or `R`, skipping elements of other
+    /// types.
+    ///
+    /// If `L` and `R` are the same type, its elements are yielded as `Either::Left`.
+    pub fn iter_types<L: Any, R: Any>(&self) -> impl Iterator<Item = Either<&L, &R>> + '_ {
+        let (left, right) = (TypeId::of::<L>(), TypeId::of::<R>());
+        self.meta.iter().filter_map(move |meta| unsafe {
+            if meta.type_id == left {
+                Some(Either::Left(self.element_ref(meta)))
+            } else if meta.type_id == right {
+                Some(Either::Right(self.element_ref(meta)))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns references to all elements of type `T`, in order.
+    pub fn collect_type<T: Any>(&self) -> Vec<&T> {
+        self.iter_type().collect()
+    }
+
+    /// Returns a new vector of clones of all elements of type `T`, in order.
+    ///
+    /// The clones are pushed via `push_cloneable`.
+    pub fn clone_type<T: Any + Clone>(&self) -> Self {
+        let count = self.iter_type::<T>().count();
+        let mut vec = CapacityPlan::new().add::<T>(count).build_inline();
+        for value in self.iter_type::<T>() {
+            vec.push_cloneable(value.clone());
+        }
+        vec
+    }
+
+    /// Returns the positions of all elements of type `T` equal to `needle`, in order.
+    pub fn find_all<T: Any + PartialEq>(&self, needle: &T) -> Vec<usize> {
+        let type_id = TypeId::of::<T>();
+        self.meta
+            .iter()
+            .enumerate()
+            .filter(|&(_, meta)| {
+                meta.type_id == type_id && unsafe { self.element_ref::<T>(meta) == needle }
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Folds every element of type `T` into an accumulator, skipping elements of other types.
+    pub fn fold_type<T: Any, B, F: FnMut(B, &T) -> B>(&self, init: B, mut f: F) -> B {
+        let type_id = TypeId::of::<T>();
+        let mut acc = init;
+        for meta in &self.meta {
+            if meta.type_id == type_id {
+                acc = f(acc, unsafe { self.element_ref(meta) });
+            }
+        }
+        acc
+    }
+
+    /// Returns an iterator over the maximal runs of consecutive elements of the same type,
+    /// as their type and index range.
+    pub fn runs(&self) -> impl Iterator<Item = (TypeId, Range<usize>)> + '_ {
+        let mut start = 0;
+        (1..self.meta.len() + 1).filter_map(move |end| {
+            let type_id = self.meta[start].type_id;
+            if end < self.meta.len() && self.meta[end].type_id == type_id {
+                return None;
+            }
+            let run = start..end;
+            start = end;
+            Some((type_id, run))
+        })
+    }
+
+    /// Returns the element at position `index` if it is of type `T`, or its type and raw
+    /// bytes otherwise.
+    ///
+    /// As with `raw_iter`, padding bytes inside the element's type are uninitialized.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn get_or_bytes<T: Any>(
+        &self,
+        index: usize,
+    ) -> Result<&T, (TypeId, &[MaybeUninit<u8>])> {
+        let meta = &self.meta[index];
+        if meta.type_id == TypeId::of::<T>() {
+            unsafe { Ok(self.element_ref(meta)) }
+        } else {
+            let bytes = unsafe {
+                let data = self.data.as_ptr().add(meta.data_index) as *const MaybeUninit<u8>;
+                slice::from_raw_parts(data, meta.type_size)
+            };
+            Err((meta.type_id, bytes))
+        }
+    }
+
+    /// Returns the elements in `range` as a slice, if they are all of type `T`.
+    ///
+    /// Returns an error if padding separates the elements; `compact` removes it.
+    pub fn slice<T: Any>(&self, range: Range<usize>) -> Result<&[T], AnyVecError> {
+        for index in range.clone() {
+            self.checked_meta::<T>(index)?;
+        }
+        if range.start >= range.end {
+            return Ok(&[]);
+        }
+        if !self.is_contiguous(range.clone()) {
+            return Err(AnyVecError::NotContiguous {
+                start: range.start,
+                end: range.end,
+            });
+        }
+        unsafe {
+            let first = self.data.as_ptr().add(self.meta[range.start].data_index) as *const T;
+            Ok(slice::from_raw_parts(first, range.end - range.start))
+        }
+    }
+
+    /// Returns the elements in `range` as a mutable slice, if they are all of type `T`.
+    ///
+    /// Returns an error if padding separates the elements; `compact` removes it.
+    pub fn slice_mut<T: Any>(&mut self, range: Range<usize>) -> Result<&mut [T], AnyVecError> {
+        if self.slice::<T>(range.clone())?.is_empty() {
+            return Ok(&mut []);
+        }
+        unsafe {
+            let data_index = self.meta[range.start].data_index;
+            let first = self.data.as_mut_ptr().add(data_index) as *mut T;
+            Ok(slice::from_raw_parts_mut(first, range.end - range.start))
+        }
+    }
+
+    /// Returns an iterator over all overlapping windows of `size` elements, if all elements
+    /// are of type `T`.
+    ///
+    /// Returns an error if padding separates the elements; `compact` removes it.
+    ///
+    /// # Panics
+    /// Panics if `size` is 0.
+    pub fn windows<T: Any>(&self, size: usize) -> Result<impl Iterator<Item = &[T]>, AnyVecError> {
+        Ok(self.slice::<T>(0..self.meta.len())?.windows(size))
+    }
+
+    /// Returns an iterator over the type and the raw bytes of each element.
+    ///
+    /// The byte slices exclude alignment padding between elements, but not the padding inside
+    /// an element's type, whose bytes are uninitialized like in `as_bytes`.
+    pub fn raw_iter(&self) -> impl Iterator<Item = (TypeId, &[MaybeUninit<u8>])> {
+        self.meta.iter().map(move |meta| {
+            let bytes = unsafe {
+                let data = self.data.as_ptr().add(meta.data_index) as *const MaybeUninit<u8>;
+                slice::from_raw_parts(data, meta.type_size)
+            };
+            (meta.type_id, bytes)
+        })
+    }
+
+    /// Returns an iterator over the index, type and raw bytes of each element.
+    ///
+    /// The byte slices are the ones of `raw_iter`.
+    pub fn enumerate_typed(&self) -> impl Iterator<Item = (usize, TypeId, &[MaybeUninit<u8>])> {
+        self.raw_iter()
+            .enumerate()
+            .map(|(index, (type_id, bytes))| (index, type_id, bytes))
+    }
+
+    /// Returns an iterator over the type and the mutable raw bytes of each element.
+    ///
+    /// The byte slices are disjoint, and exclude alignment padding between elements like
+    /// `raw_iter`.
+    ///
+    /// # Safety
+    /// The bytes written must leave a valid value of each element's type, as with
+    /// `mem::transmute`. The padding inside an element's type is uninitialized, so only use
+    /// this for padding-free types.
+    pub unsafe fn raw_iter_mut(&mut self) -> impl Iterator<Item = (TypeId, &mut [u8])> + '_ {
+        let data = self.data.as_mut_ptr();
+        self.meta.iter().map(move |meta| {
+            let bytes = slice::from_raw_parts_mut(data.add(meta.data_index), meta.type_size);
+            (meta.type_id, bytes)
+        })
+    }
+
+    /// Returns the bytes of all elements, including the padding between them.
+    ///
+    /// Padding bytes are uninitialized, both the ones between elements and the ones inside
+    /// an element's type.
+    pub fn as_bytes(&self) -> &[MaybeUninit<u8>] {
+        unsafe { slice::from_raw_parts(self.data.as_ptr() as *const _, self.data.len()) }
+    }
+
+    /// Returns an iterator over the type, data index and size of each element.
+    ///
+    /// Together with `as_bytes`, this describes the vector for `from_raw_parts`.
+    pub fn meta_entries(&self) -> impl Iterator<Item = (TypeId, usize, usize)> + '_ {
+        self.meta
+            .iter()
+            .map(|meta| (meta.type_id, meta.data_index, meta.type_size))
+    }
+
+    /// Returns an iterator over boxed clones of the elements.
+    ///
+    /// Skips the elements not pushed via `push_cloneable`.
+    pub fn iter_cloned_any(&self) -> impl Iterator<Item = Box<dyn Any>> + '_ {
+        self.meta.iter().filter_map(move |meta| {
+            let clone_box_glue = meta.clone_box_glue?;
+            unsafe { Some(clone_box_glue(self.data.as_ptr().add(meta.data_index))) }
+        })
+    }
+
+    /// Returns an iterator casting each element to `Dyn`, or yielding `None` for elements
+    /// not pushed via `push_dyn` with that `Dyn`.
+    pub fn iter_dyn<Dyn: ?Sized + 'static>(&self) -> impl Iterator<Item = Option<&Dyn>> + '_ {
+        self.meta.iter().map(move |meta| match meta.dyn_glue {
+            Some((type_id, cast)) if type_id == TypeId::of::<Dyn>() => unsafe {
+                // `&T` and `*const u8` are ABI compatible, just like `&Dyn` and `*const Dyn`.
+                let cast = mem::transmute::<fn(), fn(*const u8) -> *const Dyn>(cast);
+                Some(&*cast(self.data.as_ptr().add(meta.data_index)))
+            },
+            _ => None,
+        })
+    }
+
+    /// Feeds the length, and the type and raw bytes of each element into `state`.
+    ///
+    /// This is no `Hash` implementation, as equal values need not have equal bytes:
+    /// `0.0` and `-0.0` compare equal but differ in their bytes, and heap-owning types like
+    /// `String` hash their pointers rather than their contents.
+    ///
+    /// # Safety
+    /// The types of all elements must be free of padding, like integers and arrays of them:
+    /// the padding bytes inside a type are uninitialized, and reading them is undefined
+    /// behavior.
+    pub unsafe fn content_hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.meta.len());
+        for (type_id, bytes) in self.raw_iter() {
+            type_id.hash(state);
+            state.write(slice::from_raw_parts(bytes.as_ptr() as *const u8, bytes.len()));
+        }
+    }
+
+    /// Compares the elements of the vectors lexicographically.
+    ///
+    /// Returns `None` if elements at the same position differ in type, or were not pushed
+    /// via `push_ord`.
+    pub fn content_cmp(&self, other: &Self) -> Option<Ordering> {
+        for (a, b) in self.meta.iter().zip(&other.meta) {
+            if a.type_id != b.type_id {
+                return None;
+            }
+            let cmp_glue = a.cmp_glue.or(b.cmp_glue)?;
+            let ordering = unsafe {
+                cmp_glue(
+                    self.data.as_ptr().add(a.data_index),
+                    other.data.as_ptr().add(b.data_index),
+                )
+            };
+            if ordering != Ordering::Equal {
+                return Some(ordering);
+            }
+        }
+        Some(self.meta.len().cmp(&other.meta.len()))
+    }
+
+    /// Appends an element to the back of a collection.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`, or if the vector
+    /// is locked to another type.
+    pub fn push<T: Any>(&mut self, value: T) {
+        let index = self.meta.len();
+        self.insert(index, value);
+    }
+
+    /// Appends an element to the back of a collection if that needs no reallocation, or
+    /// returns the element otherwise.
+    ///
+    /// Both the data, including the alignment padding before the element, and the metadata
+    /// must have room for it, and the buffer must already be aligned for `T`.
+    ///
+    /// # Panics
+    /// Panics if the vector is locked to another type.
+    pub fn push_within_capacity<T: Any>(&mut self, value: T) -> Result<(), T> {
+        let data_end = align_up(self.data.len(), mem::align_of::<T>()) + mem::size_of::<T>();
+        if mem::align_of::<T>() > self.data.align()
+            || data_end > self.data.capacity()
+            || self.meta.len() == self.meta.capacity()
+        {
+            return Err(value);
+        }
+        self.push(value);
+        Ok(())
+    }
+
+    /// Appends an element to the back of a collection, or returns an error if the vector is
+    /// locked to another type or the element exceeds its maximum element size.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn try_push<T: Any>(&mut self, value: T) -> Result<(), AnyVecError> {
+        self.check_insertable(TypeId::of::<T>(), mem::size_of::<T>())?;
+        self.push(value);
+        Ok(())
+    }
+
+    /// Appends an element to the back of a collection, recording that it is `Send`.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn push_send<T: Any + Send>(&mut self, value: T) {
+        let index = self.meta.len();
+        let mut meta = AnyMeta::of::<T>();
+        meta.send = true;
+        self.insert_meta(index, value, meta);
+    }
+
+    /// Appends an element to the back of a collection, recording that it is `Send` and `Sync`.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn push_sync<T: Any + Send + Sync>(&mut self, value: T) {
+        let index = self.meta.len();
+        let mut meta = AnyMeta::of::<T>();
+        meta.send = true;
+        meta.sync = true;
+        self.insert_meta(index, value, meta);
+    }
+
+    /// Appends an element to the back of a collection, recording how to clone it.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn push_cloneable<T: Any + Clone>(&mut self, value: T) {
+        let index = self.meta.len();
+        let mut meta = AnyMeta::of::<T>();
+        meta.clone_glue = Some(clone_glue::<T>);
+        meta.clone_box_glue = Some(clone_box_glue::<T>);
+        self.insert_meta(index, value, meta);
+    }
+
+    /// Appends an element to the back of a collection, recording the name of its type for
+    /// `type_name_of`.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn push_named<T: Any>(&mut self, value: T) {
+        let index = self.meta.len();
+        let mut meta = AnyMeta::of::<T>();
+        meta.type_name = Some(std::any::type_name::<T>());
+        self.insert_meta(index, value, meta);
+    }
+
+    /// Returns the name of the type of the element at position `index`, or `None` if the
+    /// index is out of bounds or the element was not pushed via `push_named`.
+    ///
+    /// The name is meant for debugging, like `std::any::type_name`.
+    pub fn type_name_of(&self, index: usize) -> Option<&'static str> {
+        self.meta.get(index)?.type_name
+    }
+
+    /// Appends an element to the back of a collection, and returns a handle to it.
+    ///
+    /// Handles only resolve in this vector: moving an element to another vector, e.g. via
+    /// `append` or `split_off`, drops its handle.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn push_handle<T: Any>(&mut self, value: T) -> Handle {
+        if self.handles.free.is_empty() && self.handles.generations.len().is_power_of_two() {
+            self.reclaim_handles();
+        }
+        let index = match self.handles.free.pop() {
+            Some(index) => index,
+            None => {
+                self.handles.generations.push(0);
+                self.handles.generations.len() - 1
+            }
+        };
+        let handle = Handle {
+            index,
+            generation: self.handles.generations[index],
+        };
+
+        let len = self.meta.len();
+        let mut meta = AnyMeta::of::<T>();
+        meta.handle = Some(handle);
+        self.insert_meta(len, value, meta);
+        handle
+    }
+
+    /// Returns the current position of the element referred to by `handle`, or `None` if it
+    /// was removed.
+    ///
+    /// Searches the vector, so this takes linear time.
+    pub fn index_of_handle(&self, handle: Handle) -> Option<usize> {
+        if self.handles.generations.get(handle.index) != Some(&handle.generation) {
+            return None;
+        }
+        self.meta.iter().position(|meta| meta.handle == Some(handle))
+    }
+
+    /// Returns a reference to the element referred to by `handle`, or `None` if it was
+    /// removed or is not of type `T`.
+    pub fn get_by_handle<T: Any>(&self, handle: Handle) -> Option<&T> {
+        let index = self.index_of_handle(handle)?;
+        let meta = self.checked_meta::<T>(index).ok()?;
+        unsafe { Some(self.element_ref(meta)) }
+    }
+
+    /// Appends an element to the back of a collection, recording how to compare it.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn push_ord<T: Any + Ord>(&mut self, value: T) {
+        let index = self.meta.len();
+        let mut meta = AnyMeta::of::<T>();
+        meta.cmp_glue = Some(cmp_glue::<T>);
+        self.insert_meta(index, value, meta);
+    }
+
+    /// Appends an element to the back of a collection, recording how to cast it to `Dyn`.
+    ///
+    /// `Dyn` is usually a trait object type, with a cast like `|x| x as &dyn Trait`.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn push_dyn<T: Any, Dyn: ?Sized + 'static>(&mut self, value: T, cast: fn(&T) -> &Dyn) {
+        let index = self.meta.len();
+        let mut meta = AnyMeta::of::<T>();
+        let cast = unsafe { mem::transmute::<fn(&T) -> &Dyn, fn()>(cast) };
+        meta.dyn_glue = Some((TypeId::of::<Dyn>(), cast));
+        self.insert_meta(index, value, meta);
+    }
+
+    /// Appends the value in `value` to the back of a collection, and frees the box.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`, or if the vector
+    /// is locked to another type.
+    pub fn push_boxed(&mut self, value: Box<dyn Any>) {
+        self.push_box(value);
+    }
+
+    /// Returns a mutable reference to the last element if it is of type `T`, or appends `f()`
+    /// and returns a mutable reference to it otherwise.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`, or if the vector
+    /// is locked to another type.
+    pub fn push_or_get_last_mut<T: Any, F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
+        let type_id = TypeId::of::<T>();
+        if self.meta.last().map(|meta| meta.type_id) != Some(type_id) {
+            self.push(f());
+        }
+        let data_index = self.meta[self.meta.len() - 1].data_index;
+        unsafe { &mut *(self.data.as_mut_ptr().add(data_index) as *mut T) }
+    }
+
+    /// Appends an element to the back of a collection, constructed by `f` in place.
+    ///
+    /// `f` receives a pointer to the aligned, uninitialized slot of the element. If `f`
+    /// panics, nothing is appended.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`, or if the vector
+    /// is locked to another type.
+    ///
+    /// # Safety
+    /// `f` must fully initialize the value at the pointer.
+    pub unsafe fn emplace<T: Any, F: FnOnce(*mut T)>(&mut self, f: F) {
+        if let Err(err) = self.check_insertable(TypeId::of::<T>(), mem::size_of::<T>()) {
+            panic!("{}", err);
+        }
+        let mut meta = AnyMeta::of::<T>();
+        let data_len = self.data.len();
+        let data_index = self.make_room(self.meta.len(), meta.type_size, meta.type_align);
+        // Hide the room until `f` returns, so a panic in `f` leaves the data as it was.
+        self.data.set_len(data_len);
+        f(self.data.as_mut_ptr().add(data_index) as *mut T);
+        self.data.set_len(data_index + meta.type_size);
+        meta.data_index = data_index;
+        self.meta.push(meta);
+    }
+
+    /// Returns if all elements were recorded to be `Send`.
+    pub fn is_send(&self) -> bool {
+        self.meta.iter().all(|meta| meta.send)
+    }
+
+    /// Returns if all elements were recorded to be `Sync`.
+    pub fn is_sync(&self) -> bool {
+        self.meta.iter().all(|meta| meta.sync)
+    }
+
+    /// Converts the vector into a `SendAnyVec`, or returns it unchanged if not all elements
+    /// were recorded to be `Send` or if a drop observer is set.
+    #[allow(clippy::result_large_err)]
+    pub fn into_sendable(self) -> Result<SendAnyVec<N>, Self> {
+        if self.is_send() && self.drop_observer.is_none() {
+            Ok(SendAnyVec { vec: self })
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Returns the last element of the vector, or `None` if it is empty.
+    pub fn pop<T: Any>(&mut self) -> Result<Option<T>, AnyVecError> {
+        let meta = match self.meta.last() {
+            Some(meta) => *meta,
+            None => return Ok(None),
+        };
+        check_type(&meta, TypeId::of::<T>())?;
+
+        // The last element has no tail to shift, so move it out and cut off the data.
+        let mut element = MaybeUninit::<T>::uninit();
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.data.as_ptr().add(meta.data_index),
+                element.as_mut_ptr() as *mut u8,
+                meta.type_size,
+            );
+        }
+        self.forget_last();
+        unsafe { Ok(Some(element.assume_init())) }
+    }
+
+    /// Removes the last element of the vector and returns it boxed, without naming its type.
+    ///
+    /// Returns `None` if the vector is empty, or for an element from `from_raw_parts` or
+    /// `insert_bytes`, whose type is unknown, leaving the vector unchanged.
+    pub fn pop_any(&mut self) -> Option<Box<dyn Any>> {
+        let meta = *self.meta.last()?;
+        let element = unsafe { meta.box_element(self.data.as_mut_ptr().add(meta.data_index))? };
+        self.forget_last();
+        Some(element)
+    }
+
+    /// Moves all the elements of `other` into `Self`, leaving `other` empty.
+    ///
+    /// Reserves the room for them up front, so the data and the metadata are reallocated at
+    /// most once each.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn append(&mut self, other: &mut Self) {
+        // Padding for an empty `other` would leave the data ending past the last element.
+        if other.is_empty() {
+            return;
+        }
+        let org_meta_size = self.meta.len();
+        let data_index = align_up(self.data.len(), other.data.align());
+
+        self.reserve_for_append(other);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                other.data.as_ptr(),
+                self.data.as_mut_ptr().add(data_index),
+                other.data.len(),
+            );
+            self.data.set_len(data_index + other.data.len());
+            other.zeroize(0..other.data.len());
+            other.data.set_len(0);
+        }
+
+        self.meta.append(&mut other.meta);
+        for meta in self.meta.iter_mut().skip(org_meta_size) {
+            meta.data_index = rebase(meta.data_index, 0, data_index);
+            meta.handle = None;
+        }
+    }
+
+    /// Moves all the elements of `other` to the front of `Self`, leaving `other` empty.
+    ///
+    /// Shifts the elements of `Self` only once.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn prepend(&mut self, other: &mut Self) {
+        self.data.align_to(other.data.align());
+        self.move_tail(0, other.data.len());
+        unsafe {
+            ptr::copy_nonoverlapping(
+                other.data.as_ptr(),
+                self.data.as_mut_ptr(),
+                other.data.len(),
+            );
+            other.zeroize(0..other.data.len());
+            other.data.set_len(0);
+        }
+        insert_metas(
+            &mut self.meta,
+            0,
+            other.meta.drain(..).map(|mut meta| {
+                meta.handle = None;
+                meta
+            }),
+        );
+    }
+
+    /// Appends clones of all the elements of `other` to `Self`, leaving `other` unchanged.
+    ///
+    /// Fails without appending anything if an element of `other` was not pushed as cloneable.
+    ///
+    /// # Panics
+    /// Panics if the number of elements in the vector overflows a `usize`.
+    pub fn append_cloned(&mut self, other: &Self) -> Result<(), AnyVecError> {
+        if let Some(index) = other.meta.iter().position(|meta| meta.clone_glue.is_none()) {
+            return Err(AnyVecError::NotCloneable(index));
+        }
+
+        self.data.reserve(other.data.len());
+        self.meta.reserve(other.meta.len());
+        self.data.align_to(other.data.align());
+        for meta in &other.meta {
+            let mut meta = *meta;
+            let data_index = self.make_room(self.meta.len(), meta.type_size, meta.type_align);
+            unsafe {
+                let clone_glue = meta.clone_glue.unwrap();
+                clone_glue(
+                    other.data.as_ptr().add(meta.data_index),
+                    self.data.as_mut_ptr().add(data_index),
+                );
+            }
+            meta.data_index = data_index;
+            meta.handle = None;
+            self.meta.push(meta);
+        }
+        Ok(())
+    }
+
+    /// Removes and drops every element of type `T`, returning how many were removed.
+    ///
+    /// Keeps the order of the remaining elements.
+    pub fn clear_type<T: Any>(&mut self) -> usize {
+        let type_id = TypeId::of::<T>();
+        let mut observer = self.drop_observer.take();
+        let removed = unsafe {
+            self.retain_raw(|meta, ptr| {
+                if meta.type_id == type_id {
+                    drop_observed(&mut observer, meta, ptr);
+                    false
+                } else {
+                    true
+                }
+            })
+        };
+        self.drop_observer = observer;
+        removed
+    }
+
+    /// Retains only the elements for which `f`, given their index and type, returns `true`.
+    ///
+    /// Drops the other elements and keeps the order of the retained ones.
+    pub fn retain_any<F: FnMut(usize, TypeId) -> bool>(&mut self, mut f: F) {
+        let mut index = 0;
+        let mut observer = self.drop_observer.take();
+        unsafe {
+            self.retain_raw(|meta, ptr| {
+                let keep = f(index, meta.type_id);
+                index += 1;
+                if !keep {
+                    drop_observed(&mut observer, meta, ptr);
+                }
+                keep
+            });
+        }
+        self.drop_observer = observer;
+    }
+
+    /// Retains only the elements of type `T` for which `keep` returns `true`, and the elements
+    /// of other types.
+    ///
+    /// Passes each removed element to `on_remove` instead of dropping it, and keeps the order
+    /// of the retained ones.
+    pub fn retain_with<T: Any, F: FnMut(&T) -> bool, G: FnMut(T)>(
+        &mut self,
+        mut keep: F,
+        mut on_remove: G,
+    ) {
+        unsafe {
+            self.retain_raw(|meta, ptr| {
+                if meta.type_id != TypeId::of::<T>() || keep(&*(ptr as *const T)) {
+                    return true;
+                }
+                on_remove(ptr::read(ptr as *const T));
+                false
+            });
+        }
+    }
+
+    /// Removes the elements of type `T` for which `f` returns `true`, and returns them in a
+    /// new vector.
+    ///
+    /// Keeps the order of both the remaining and the extracted elements.
+    pub fn extract_if<T: Any, F: FnMut(&T) -> bool>(&mut self, mut f: F) -> Self {
+        let mut extracted = Self::new();
+        extracted.zeroize_on_remove = self.zeroize_on_remove;
+        extracted.data.growth = self.data.growth;
+        unsafe {
+            self.retain_raw(|meta, ptr| {
+                if meta.type_id != TypeId::of::<T>() || !f(&*(ptr as *const T)) {
+                    return true;
+                }
+                let index = extracted.len();
+                let meta = AnyMeta { handle: None, ..*meta };
+                extracted.insert_meta(index, ptr::read(ptr as *const T), meta);
+                false
+            });
+        }
+        extracted
+    }
+
+    /// Removes the elements at the positions in `indices`, and returns them in a new vector.
+    ///
+    /// Repeated indices are removed once. Keeps the order of both the remaining and the
+    /// removed elements, and moves the remaining ones in a single pass.
+    ///
+    /// # Panics
+    /// Panics if an index is out of bounds.
+    pub fn remove_indices(&mut self, indices: &[usize]) -> Self {
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        if let Some(&index) = indices.last() {
+            assert!(
+                index < self.meta.len(),
+                "index (is {}) should be < len (is {})",
+                index,
+                self.meta.len()
+            );
+        }
+
+        let mut removed = Self::new();
+        removed.zeroize_on_remove = self.zeroize_on_remove;
+        removed.data.growth = self.data.growth;
+        removed.meta.reserve(indices.len());
+        let mut indices = indices.into_iter().peekable();
+        let mut index = 0;
+        unsafe {
+            self.retain_raw(|meta, ptr| {
+                let keep = indices.peek() != Some(&index);
+                if !keep {
+                    indices.next();
+                    removed.push_raw(meta, ptr);
+                }
+                index += 1;
+                keep
+            });
+        }
+        removed
+    }
+
+    /// Compacts the vector, shrinks it to fit and makes it read-only.
+    pub fn freeze(mut self) -> FrozenAnyVec<N> {
+        self.compact();
+        FrozenAnyVec { vec: self }
+    }
+
+    /// Saves a copy of the contents of the vector, see `AnyVecSnapshot` for how deep it goes.
+    ///
+    /// Fails if an element was not pushed as cloneable.
+    pub fn snapshot(&self) -> Result<AnyVecSnapshot<N>, AnyVecError> {
+        let mut vec = Self::new();
+        vec.append_cloned(self)?;
+        Ok(AnyVecSnapshot { vec })
+    }
+
+    /// Replaces the contents of the vector with those saved in `snapshot`.
+    ///
+    /// The current elements are dropped. The settings, like the type lock, zeroize setting
+    /// and growth strategy, are left unchanged.
+    pub fn restore(&mut self, mut snapshot: AnyVecSnapshot<N>) {
+        self.clear();
+        // The growth strategy is kept in the data buffer, so it must move with it.
+        snapshot.vec.data.growth = self.data.growth;
+        mem::swap(&mut self.data, &mut snapshot.vec.data);
+        mem::swap(&mut self.meta, &mut snapshot.vec.meta);
+    }
+
+    /// Sets a callback told the type of every element the vector drops, replacing any
+    /// previous one.
+    ///
+    /// This is a debugging aid for finding leaks. It covers the elements dropped by `remove`,
+    /// `truncate`, `clear`, `clear_type`, `retain_any` and the destructor of the vector, but
+    /// not the elements moved out of it. Vectors split off or extracted from this one do not
+    /// inherit the callback.
+    pub fn set_drop_observer(&mut self, observer: Box<dyn FnMut(TypeId)>) {
+        self.drop_observer = Some(DropObserver(observer));
+    }
+
+    /// Removes and returns the callback set by `set_drop_observer`.
+    pub fn take_drop_observer(&mut self) -> Option<Box<dyn FnMut(TypeId)>> {
+        self.drop_observer.take().map(|DropObserver(f)| f)
+    }
+
+    /// Clears the vector.
+    ///
+    /// Keeps the allocated capacity for reuse.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Clears the vector and frees its allocated capacity.
+    pub fn clear_and_shrink(&mut self) {
+        self.clear();
+        self.shrink_to_fit();
+    }
+
+    /// Returns a cursor pointing at the first element, which can walk the vector and remove
+    /// elements on the way.
+    pub fn cursor_mut(&mut self) -> Cursor<'_, N> {
+        Cursor {
+            vec: self,
+            index: 0,
+        }
+    }
+
+    /// Returns an iterator moving each element out of the vector into a box, leaving the
+    /// vector empty.
+    ///
+    /// Keeps the allocated capacity for reuse. The elements not yielded when the iterator is
+    /// dropped are dropped. Elements whose type is unknown, from `from_raw_parts` or
+    /// `insert_bytes`, are dropped instead of yielded.
+    pub fn drain_all(&mut self) -> impl Iterator<Item = Box<dyn Any>> + '_ {
+        let len = self.meta.len();
+        // Hide the elements, so leaking the iterator leaks them instead of dropping them twice.
+        unsafe { self.meta.set_len(0) };
+        DrainAll {
+            vec: self,
+            next: 0,
+            len,
+        }
+    }
+
+    /// Returns the number of elements in the vector.
+    pub fn len(&self) -> usize {
+        self.meta.len()
+    }
+
+    /// Returns if the vector is empty.
+    pub fn is_empty(&self) -> bool {
+        self.meta.is_empty()
+    }
+
+    /// Splits the collection into two at the given index.
+    ///
+    /// # Panics
+    /// Panics if `at > len`.
+    pub fn split_off(&mut self, at: usize) -> Self {
+        assert!(
+            at <= self.meta.len(),
+            "`at` split index (is {}) should be <= len (is {})",
+            at,
+            self.meta.len()
+        );
+
+        // Keep the offsets of the tail aligned by moving it by a multiple of its alignment.
+        let align = self.meta[at..].iter().map(|meta| meta.type_align).max().unwrap_or(1);
+        let data_index = match self.meta.get(at) {
+            Some(meta) => align_down(meta.data_index, align),
+            None => self.data.len(),
+        };
+        let data_len = self.data.len() - data_index;
+
+        let mut other_data = AnyData::with_capacity(data_len, align);
+        other_data.growth = self.data.growth;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.data.as_ptr().add(data_index),
+                other_data.as_mut_ptr(),
+                data_len,
+            );
+            other_data.set_len(data_len);
+            let data_end = self.data_end(at);
+            self.data.set_len(data_end);
+            self.zeroize(data_end..data_index + data_len);
+        }
+        let mut other_meta: MetaBuf<N> = self.meta.drain(at..).collect();
+
+        for meta in other_meta.iter_mut() {
+            meta.data_index = rebase(meta.data_index, data_index, 0);
+            meta.handle = None;
+        }
+
+        Self {
+            data: other_data,
+            meta: other_meta,
+            locked_type: self.locked_type,
+            max_element_size: self.max_element_size,
+            zeroize_on_remove: self.zeroize_on_remove,
+            handles: HandleTable::default(),
+            drop_observer: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Splits the collection into two at the given index, or returns an error if `at > len`.
+    ///
+    /// The vector is left unchanged on error.
+    pub fn try_split_off(&mut self, at: usize) -> Result<Self, AnyVecError> {
+        if at > self.meta.len() {
+            return Err(AnyVecError::OutOfBounds {
+                index: at,
+                len: self.meta.len(),
+            });
+        }
+        Ok(self.split_off(at))
+    }
+
+    /// Removes the alignment padding between elements, returning the number of bytes saved.
+    ///
+    /// Also shrinks the capacity of the vector as much as possible.
+    pub fn compact(&mut self) -> usize {
+        let data_len = self.data.len();
+        unsafe {
+            self.retain_raw(|_, _| true);
+        }
+        self.shrink_to_fit();
+        data_len - self.data.len()
+    }
+
+    /// Recomputes the data index of every element from the sizes and alignments of the
+    /// elements before it, without moving any bytes.
+    ///
+    /// Afterwards, the elements are laid out like after `compact`.
+    ///
+    /// # Panics
+    /// Panics if the recomputed layout does not fit in the data.
+    ///
+    /// # Safety
+    /// The bytes must already be laid out that way, e.g. after editing them externally.
+    pub unsafe fn recompute_offsets(&mut self) {
+        let mut data_end = 0;
+        for meta in self.meta.iter_mut() {
+            meta.data_index = align_up(data_end, meta.type_align);
+            data_end = meta.data_index + meta.type_size;
+        }
+        assert!(
+            data_end <= self.data.len(),
+            "recomputed layout (is {} bytes) should fit in the data (is {} bytes)",
+            data_end,
+            self.data.len()
+        );
+        self.data.set_len(data_end);
+    }
+
+    /// Returns the number of bytes taken by the elements, including the padding between them.
+    pub fn data_len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns the share of the data taken by padding rather than elements, between 0 and 1.
+    ///
+    /// Counts both the padding `compact` removes and the padding alignment requires.
+    pub fn fragmentation_ratio(&self) -> f64 {
+        if self.data.len() == 0 {
+            return 0.0;
+        }
+        let elements: usize = self.meta.iter().map(|meta| meta.type_size).sum();
+        (self.data.len() - elements) as f64 / self.data.len() as f64
+    }
+
+    /// Returns the padding pushing elements of the given sizes and alignments in order into an
+    /// empty vector inserts between them.
+    ///
+    /// # Panics
+    /// Panics if an alignment is not a power of two.
+    pub fn predict_padding(type_sizes_and_aligns: &[(usize, usize)]) -> usize {
+        let mut data_end = 0;
+        let mut padding = 0;
+        for &(size, align) in type_sizes_and_aligns {
+            assert!(align.is_power_of_two(), "alignment (is {}) should be a power of two", align);
+            let data_index = align_up(data_end, align);
+            padding += data_index - data_end;
+            data_end = data_index + size;
+        }
+        padding
+    }
+
+    /// Returns the bytes taken by the elements of each type, excluding padding.
+    pub fn bytes_by_type(&self) -> HashMap<TypeId, usize> {
+        let mut bytes = HashMap::new();
+        for meta in self.meta.iter() {
+            *bytes.entry(meta.type_id).or_insert(0) += meta.type_size;
+        }
+        bytes
+    }
+
+    /// Returns the positions of the elements sorted by where their data is stored.
+    ///
+    /// This is diagnostic only: every method of `AnyVec` keeps the data of the elements in
+    /// the order of their positions, so the result is `0..len`, unless the layout is corrupt.
+    pub fn layout_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.meta.len()).collect();
+        order.sort_by_key(|&index| self.meta[index].data_index);
+        order
+    }
+
+    /// Checks the layout of the elements in debug builds, panicking if it is corrupt.
+    ///
+    /// The elements must be in ascending order without overlapping, aligned, and end within
+    /// the data. This is a no-op in release builds.
+    pub fn debug_assert_consistent(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        assert_eq!(self.data.as_ptr() as usize % self.data.align(), 0, "data is misaligned");
+        let mut data_end = 0;
+        for (index, meta) in self.meta.iter().enumerate() {
+            assert!(
+                meta.data_index >= data_end,
+                "element {} overlaps the element before it",
+                index
+            );
+            assert!(
+                meta.data_index % meta.type_align == 0 && meta.type_align <= self.data.align(),
+                "element {} is misaligned",
+                index
+            );
+            data_end = meta.data_index + meta.type_size;
+        }
+        assert_eq!(data_end, self.data.len(), "data does not end with the last element");
+    }
+
+    /// Estimates the bytes saved by storing the elements inline, compared to a
+    /// `Vec<Box<dyn Any>>` holding a fat pointer and a heap allocation for each element.
+    ///
+    /// This is informational only: it counts neither the metadata `AnyVec` keeps per element
+    /// nor any allocator overhead.
+    pub fn savings_vs_boxed(&self) -> isize {
+        let pointers = self.meta.len() * mem::size_of::<*mut ()>() * 2;
+        let elements: usize = self.meta.iter().map(|meta| meta.type_size).sum();
+        (pointers + elements) as isize - self.data.len() as isize
+    }
+
+    /// Merges two vectors of `T` sorted by `cmp` into one sorted vector, in a single pass.
+    ///
+    /// The merge is stable: of equal elements, those of `self` come first. Fails if an element
+    /// of either vector is not of type `T`.
+    pub fn merge_sorted<T: Any, F: FnMut(&T, &T) -> Ordering>(
+        mut self,
+        mut other: Self,
+        mut cmp: F,
+    ) -> Result<Self, AnyVecError> {
+        self.check_homogeneous::<T>()?;
+        other.check_homogeneous::<T>()?;
+
+        let (len_a, len_b) = (self.meta.len(), other.meta.len());
+        let mut merged = CapacityPlan::new().add::<T>(len_a + len_b).build_inline();
+        merged.zeroize_on_remove = self.zeroize_on_remove;
+        merged.data.growth = self.data.growth;
+        unsafe {
+            // Hide the elements of both vectors, so a panic in `cmp` leaks the ones not merged
+            // yet instead of dropping the merged ones twice.
+            self.meta.set_len(0);
+            other.meta.set_len(0);
+            let (metas_a, metas_b) = (self.meta.as_ptr(), other.meta.as_ptr());
+            let (mut i, mut j) = (0, 0);
+            while i < len_a || j < len_b {
+                let take_a = j == len_b || {
+                    i < len_a && {
+                        let (x, y) = (&*metas_a.add(i), &*metas_b.add(j));
+                        cmp(self.element_ref(x), other.element_ref(y)) != Ordering::Greater
+                    }
+                };
+                if take_a {
+                    let meta = &*metas_a.add(i);
+                    merged.push_raw(meta, self.data.as_ptr().add(meta.data_index));
+                    i += 1;
+                } else {
+                    let meta = &*metas_b.add(j);
+                    merged.push_raw(meta, other.data.as_ptr().add(meta.data_index));
+                    j += 1;
+                }
+            }
+        }
+        let (data_a, data_b) = (self.data.len(), other.data.len());
+        self.zeroize(0..data_a);
+        other.zeroize(0..data_b);
+        Ok(merged)
+    }
+
+    /// Moves the elements into one vector per element type, keeping their relative order.
+    pub fn partition_by_type(mut self) -> HashMap<TypeId, Self> {
+        let mut buckets: HashMap<TypeId, Self> = HashMap::new();
+        let len = self.meta.len();
+        unsafe {
+            // Hide the elements, so a panic leaks the ones not moved yet instead of dropping
+            // the moved ones twice.
+            self.meta.set_len(0);
+            let metas = self.meta.as_ptr();
+            for i in 0..len {
+                let meta = &*metas.add(i);
+                let bucket = buckets.entry(meta.type_id).or_insert_with(|| {
+                    let mut bucket = Self::new();
+                    bucket.zeroize_on_remove = self.zeroize_on_remove;
+                    bucket.data.growth = self.data.growth;
+                    bucket
+                });
+                bucket.push_raw(meta, self.data.as_ptr().add(meta.data_index));
+            }
+        }
+        let data_len = self.data.len();
+        self.zeroize(0..data_len);
+        buckets
+    }
+
+    /// Binary searches a sorted vector of `T` with a comparator function.
+    ///
+    /// Behaves like `slice::binary_search_by`, or fails if an element is not of type `T`.
+    pub fn binary_search_by<T: Any, F: FnMut(&T) -> Ordering>(
+        &self,
+        mut f: F,
+    ) -> Result<Result<usize, usize>, AnyVecError> {
+        self.check_homogeneous::<T>()?;
+
+        let mut left = 0;
+        let mut right = self.meta.len();
+        while left < right {
+            let mid = left + (right - left) / 2;
+            match f(unsafe { self.element_ref(&self.meta[mid]) }) {
+                Ordering::Less => left = mid + 1,
+                Ordering::Greater => right = mid,
+                Ordering::Equal => return Ok(Ok(mid)),
+            }
+        }
+        Ok(Err(left))
+    }
+
+    /// Sorts a vector of `T` with a comparator function.
+    ///
+    /// Sorts in place if the elements are stored back to back, or through a temporary
+    /// `Vec<T>` otherwise. Fails if an element is not of type `T`.
+    pub fn sort_by<T: Any, F: FnMut(&T, &T) -> Ordering>(
+        &mut self,
+        compare: F,
+    ) -> Result<(), AnyVecError> {
+        self.check_homogeneous::<T>()?;
+
+        let len = self.meta.len();
+        if len == 0 {
+            return Ok(());
+        }
+        unsafe {
+            let data = self.data.as_mut_ptr();
+            if self.is_contiguous(0..len) {
+                let first = data.add(self.meta[0].data_index) as *mut T;
+                slice::from_raw_parts_mut(first, len).sort_by(compare);
+            } else {
+                // Hide the elements, so a panicking `compare` drops them only once via `values`.
+                self.meta.set_len(0);
+                let metas = slice::from_raw_parts(self.meta.as_ptr(), len);
+                let mut values: Vec<T> = metas
+                    .iter()
+                    .map(|meta| ptr::read(data.add(meta.data_index) as *const T))
+                    .collect();
+                values.sort_by(compare);
+                for (meta, value) in metas.iter().zip(values) {
+                    ptr::write(data.add(meta.data_index) as *mut T, value);
+                }
+                self.meta.set_len(len);
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the elements into a `Vec<T>`, if they are all of type `T`.
+    ///
+    /// Otherwise returns the vector unchanged, together with the error.
+    #[allow(clippy::result_large_err)]
+    pub fn into_vec<T: Any>(mut self) -> Result<Vec<T>, (Self, AnyVecError)> {
+        if let Err(err) = self.check_homogeneous::<T>() {
+            return Err((self, err));
+        }
+        Ok(unsafe { self.take_all() })
+    }
+
+    /// Moves the elements into a `Vec<T>`, leaving the vector empty.
+    ///
+    /// # Safety
+    /// All elements must be of type `T`.
+    unsafe fn take_all<T: Any>(&mut self) -> Vec<T> {
+        let data = self.data.as_ptr();
+        let vec: Vec<T> = self
+            .meta
+            .iter()
+            .map(|meta| ptr::read(data.add(meta.data_index) as *const T))
+            .collect();
+        // The elements are owned by `vec` now.
+        self.meta.set_len(0);
+        let data_len = self.data.len();
+        self.zeroize(0..data_len);
+        self.data.set_len(0);
+        vec
+    }
+
+    /// Copies the elements in `src` to the positions starting at `dest`, overwriting them.
+    ///
+    /// The ranges may overlap, but all elements in both must be of type `T`.
+    ///
+    /// # Panics
+    /// Panics if `src` starts after it ends.
+    pub fn copy_within<T: Any + Copy>(
+        &mut self,
+        src: Range<usize>,
+        dest: usize,
+    ) -> Result<(), AnyVecError> {
+        assert!(src.start <= src.end, "src start is greater than src end");
+        let count = src.end - src.start;
+        let dest_range = dest..dest.saturating_add(count);
+        for index in src.clone().chain(dest_range.clone()) {
+            self.checked_meta::<T>(index)?;
+        }
+        if count == 0 {
+            return Ok(());
+        }
+
+        unsafe {
+            let data = self.data.as_mut_ptr();
+            if self.is_contiguous(src.clone()) && self.is_contiguous(dest_range.clone()) {
+                ptr::copy(
+                    data.add(self.meta[src.start].data_index) as *const T,
+                    data.add(self.meta[dest].data_index) as *mut T,
+                    count,
+                );
+            } else {
+                let values: Vec<T> = self.meta[src]
+                    .iter()
+                    .map(|meta| ptr::read(data.add(meta.data_index) as *const T))
+                    .collect();
+                for (meta, value) in self.meta[dest_range].iter().zip(values) {
+                    ptr::write(data.add(meta.data_index) as *mut T, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Overwrites the elements in `range` with clones of `value`, dropping their old values.
+    ///
+    /// Fails without changing anything if an element in `range` is out of bounds or not of
+    /// type `T`.
+    pub fn fill<T: Any + Clone>(
+        &mut self,
+        range: Range<usize>,
+        value: T,
+    ) -> Result<(), AnyVecError> {
+        for index in range.clone() {
+            self.checked_meta::<T>(index)?;
+        }
+        let data = self.data.as_mut_ptr();
+        for meta in &self.meta[range] {
+            unsafe { *(data.add(meta.data_index) as *mut T) = value.clone() };
+        }
+        Ok(())
+    }
+
+    /// Compacts the vector in a single pass, keeping the elements for which `f` returns `true`.
+    ///
+    /// `f` takes ownership of every element it rejects. Returns the number of rejected elements.
+    unsafe fn retain_raw<F: FnMut(&AnyMeta, *mut u8) -> bool>(&mut self, mut f: F) -> usize {
+        let len = self.meta.len();
+        let mut removed = 0;
+        let mut data_len = 0;
+
+        // Hide everything, so a panic in `f` leaks the elements instead of dropping them twice.
+        let data_end = self.data.len();
+        self.meta.set_len(0);
+        self.data.set_len(0);
+        let data = self.data.as_mut_ptr();
+        let metas = self.meta.as_mut_ptr();
+        for i in 0..len {
+            let mut meta = ptr::read(metas.add(i));
+            if f(&meta, data.add(meta.data_index)) {
+                data_len = align_up(data_len, meta.type_align);
+                ptr::copy(data.add(meta.data_index), data.add(data_len), meta.type_size);
+                meta.data_index = data_len;
+                data_len += meta.type_size;
+                ptr::write(metas.add(i - removed), meta);
+            } else {
+                self.zeroize(meta.data_index..meta.data_index + meta.type_size);
+                removed += 1;
+            }
+        }
+        self.meta.set_len(len - removed);
+        self.zeroize(data_len..data_end);
+        self.data.set_len(data_len);
+
+        removed
+    }
+
+    /// Removes the last element without dropping it, after it was moved out.
+    fn forget_last(&mut self) {
+        self.meta.pop();
+        let data_len = self.data.len();
+        let data_end = self.data_end(self.meta.len());
+        self.zeroize(data_end..data_len);
+        unsafe { self.data.set_len(data_end) };
+    }
+
+    /// Appends the element described by `meta` by moving its bytes from `ptr`.
+    ///
+    /// Drops its handle, which belongs to the vector it was moved from.
+    unsafe fn push_raw(&mut self, meta: &AnyMeta, ptr: *const u8) {
+        let data_index = self.make_room(self.meta.len(), meta.type_size, meta.type_align);
+        ptr::copy_nonoverlapping(ptr, self.data.as_mut_ptr().add(data_index), meta.type_size);
+        self.meta.push(AnyMeta {
+            data_index,
+            handle: None,
+            ..*meta
+        });
+    }
+
+    /// Removes the element at position `index`, after passing it to `f`.
+    ///
+    /// `f` takes ownership of the element. Moves the following elements to the left.
+    unsafe fn remove_with<F: FnOnce(&AnyMeta, *mut u8)>(&mut self, index: usize, f: F) {
+        let meta = self.meta[index];
+
+        // Hide the tail, so a panic in `f` leaks it instead of dropping it twice.
+        let len = self.meta.len();
+        self.meta.set_len(index);
+        f(&meta, self.data.as_mut_ptr().add(meta.data_index));
+        self.meta.set_len(len);
+        self.zeroize(meta.data_index..meta.data_index + meta.type_size);
+
+        self.meta.remove(index);
+        let data_len = self.data.len();
+        let data_end = self.data_end(index);
+        self.move_tail(index, data_end);
+        self.zeroize(self.data.len()..data_len);
+    }
+
+    /// Overwrites the bytes in `range` with zeros, if enabled by `set_zeroize`.
+    fn zeroize(&mut self, range: Range<usize>) {
+        if self.zeroize_on_remove {
+            unsafe { zero_bytes(self.data.as_mut_ptr().add(range.start), range.len()) };
+        }
+    }
+
+    /// Makes room for an element of `size` bytes aligned to `align` at position `index`, and
+    /// returns its data index.
+    ///
+    /// An element of size 0 takes no bytes: where the element before it ends is aligned, it is
+    /// placed there without moving or allocating anything.
+    fn make_room(&mut self, index: usize, size: usize, align: usize) -> usize {
+        let data_end = self.data_end(index);
+        if size == 0 && align <= self.data.align() && data_end.is_multiple_of(align) {
+            return data_end;
+        }
+        self.data.align_to(align);
+        let data_index = align_up(data_end, align);
+        self.move_tail(index, data_index + size);
+        data_index
+    }
+
+    /// Moves the elements from position `index` on as close to byte `start` as possible, but
+    /// not before it.
+    ///
+    /// They move by a multiple of their largest alignment, so their offsets stay aligned.
+    fn move_tail(&mut self, index: usize, start: usize) {
+        let old_start = match self.meta.get(index) {
+            Some(meta) => meta.data_index,
+            None => self.data.len(),
+        };
+        let align = self.meta[index..].iter().map(|meta| meta.type_align).max().unwrap_or(1);
+        let new_start = if start > old_start {
+            old_start + align_up(start - old_start, align)
+        } else {
+            old_start - align_down(old_start - start, align)
+        };
+        if new_start == old_start {
+            return;
+        }
+
+        let tail_len = self.data.len() - old_start;
+        if new_start > old_start {
+            self.data.reserve(new_start - old_start);
+        }
+        unsafe {
+            let data = self.data.as_mut_ptr();
+            ptr::copy(data.add(old_start), data.add(new_start), tail_len);
+            self.data.set_len(new_start + tail_len);
+        }
+        for meta in &mut self.meta[index..] {
+            meta.data_index = rebase(meta.data_index, old_start, new_start);
+        }
+    }
+
+    /// Returns the byte index where the element before position `index` ends.
+    fn data_end(&self, index: usize) -> usize {
+        match index.checked_sub(1) {
+            Some(last) => self.meta[last].data_index + self.meta[last].type_size,
+            None => 0,
+        }
+    }
+
+    /// Appends the value in `value`, and frees the box.
+    fn push_box(&mut self, value: Box<dyn Any>) {
+        let type_id = (*value).type_id();
+        let type_size = mem::size_of_val(&*value);
+        if let Err(err) = self.check_insertable(type_id, type_size) {
+            panic!("{}", err);
+        }
+        let type_align = mem::align_of_val(&*value);
+
+        let data_index = self.make_room(self.meta.len(), type_size, type_align);
+
+        let boxed = Box::into_raw(value);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                boxed as *const u8,
+                self.data.as_mut_ptr().add(data_index),
+                type_size,
+            );
+            drop(Box::from_raw(boxed as *mut ManuallyDrop<dyn Any>));
+        }
+        self.meta.push(AnyMeta {
+            data_index,
+            type_id,
+            type_size,
+            type_align,
+            drop_glue: forget_glue,
+            box_glue: None,
+            clone_glue: None,
+            clone_box_glue: None,
+            cmp_glue: None,
+            dyn_glue: None,
+            boxed: Some(Vtable(boxed)),
+            send: false,
+            sync: false,
+            handle: None,
+            type_name: None,
+        });
+    }
+
+    /// Frees the handle slots no element refers to anymore, bumping their generation.
+    fn reclaim_handles(&mut self) {
+        let table = &mut self.handles;
+        let mut used = vec![false; table.generations.len()];
+        for handle in self.meta.iter().filter_map(|meta| meta.handle) {
+            used[handle.index] = true;
+        }
+        table.free.clear();
+        for (index, generation) in table.generations.iter_mut().enumerate() {
+            if !used[index] {
+                *generation = generation.wrapping_add(1);
+                table.free.push(index);
+            }
+        }
+    }
+
+    /// Returns an error if the vector is locked to a type other than `type_id`, or if it
+    /// limits elements to less than `size` bytes.
+    fn check_insertable(&self, type_id: TypeId, size: usize) -> Result<(), AnyVecError> {
+        match self.locked_type {
+            // Like `get`, report the type asked for as `expected` and the stored one as `found`.
+            Some(locked) if locked != type_id => {
+                return Err(AnyVecError::TypeMismatch {
+                    expected: type_id,
+                    found: locked,
+                })
+            }
+            _ => {}
+        }
+        match self.max_element_size {
+            Some(max) if size > max => Err(AnyVecError::TooLarge { size, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the metadata of the element at position `index` if it is of type `T`.
+    fn checked_meta<T: Any>(&self, index: usize) -> Result<&AnyMeta, AnyVecError> {
+        let meta = match self.meta.get(index) {
+            Some(meta) => meta,
+            None => {
+                return Err(AnyVecError::OutOfBounds {
+                    index,
+                    len: self.meta.len(),
+                })
+            }
+        };
+        check_type(meta, TypeId::of::<T>())?;
+        Ok(meta)
+    }
+
+    /// Fails if not all elements are of type `T`.
+    fn check_homogeneous<T: Any>(&self) -> Result<(), AnyVecError> {
+        self.meta
+            .iter()
+            .try_for_each(|meta| check_type(meta, TypeId::of::<T>()))
+    }
+
+    /// Returns if the elements in `range` are stored back to back, without padding in between.
+    fn is_contiguous(&self, range: Range<usize>) -> bool {
+        self.meta[range]
+            .windows(2)
+            .all(|pair| pair[0].data_index + pair[0].type_size == pair[1].data_index)
+    }
+
+    /// Returns a reference to the element described by `meta`, which must be of type `T`.
+    unsafe fn element_ref<T: Any>(&self, meta: &AnyMeta) -> &T {
+        &*(self.data.as_ptr().add(meta.data_index) as *const T)
+    }
+}
+
+/// The iterator returned by `AnyVec::drain_all`.
+///
+/// Owns the metadata of the elements from `next` to `len`, which are hidden from `vec`.
+struct DrainAll<'a, const N: usize> {
+    vec: &'a mut AnyVecN<N>,
+    next: usize,
+    len: usize,
+}
+
+impl<'a, const N: usize> Iterator for DrainAll<'a, N> {
+    type Item = Box<dyn Any>;
+
+    fn next(&mut self) -> Option<Box<dyn Any>> {
+        while self.next < self.len {
+            unsafe {
+                let meta = *self.vec.meta.as_ptr().add(self.next);
+                self.next += 1;
+                let ptr = self.vec.data.as_mut_ptr().add(meta.data_index);
+                match meta.box_element(ptr) {
+                    Some(element) => return Some(element),
+                    None => drop_observed(&mut self.vec.drop_observer, &meta, ptr),
+                }
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.len - self.next))
+    }
+}
+
+impl<'a, const N: usize> Drop for DrainAll<'a, N> {
+    fn drop(&mut self) {
+        while self.next < self.len {
+            unsafe {
+                let meta = *self.vec.meta.as_ptr().add(self.next);
+                self.next += 1;
+                let ptr = self.vec.data.as_mut_ptr().add(meta.data_index);
+                drop_observed(&mut self.vec.drop_observer, &meta, ptr);
+            }
+        }
+        let data_len = self.vec.data.len();
+        self.vec.zeroize(0..data_len);
+        unsafe { self.vec.data.set_len(0) };
+    }
+}
+
+impl<const N: usize> Drop for AnyVecN<N> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+    use std::collections::hash_map::DefaultHasher;
+    use std::mem;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::thread;
+
+    struct TestData<'a> {
+        a: u64,
+        b: &'a str,
+    }
+
+    /// Reads bytes known to be initialized.
+    unsafe fn assume_init(bytes: &[MaybeUninit<u8>]) -> &[u8] {
+        slice::from_raw_parts(bytes.as_ptr() as *const u8, bytes.len())
+    }
+
+    #[test]
+    fn capacity() {
+        assert_eq!(AnyVec::with_capacity(8, 64).capacity(64), 8);
+        assert_eq!(AnyVec::with_capacity(8, 64).capacity(32), 8);
+        assert_eq!(AnyVec::with_capacity(16, 64).capacity(64), 16);
+        assert_eq!(AnyVec::with_capacity(16, 32).capacity(64), 8);
+        assert_eq!(AnyVec::with_capacity(8, 20).capacity(16), 8);
+        assert_eq!(AnyVec::with_capacity(8, 16).capacity(20), 6);
+        assert_eq!(AnyVec::with_capacity(8, 16).capacity(0), 8);
+    }
+
+    #[test]
+    fn push_within_capacity() {
+        let mut vec = CapacityPlan::new().add::<u8>(1).add::<u32>(2).build();
+        let data = vec.data.as_ptr();
+        assert_eq!(vec.push_within_capacity(1u8), Ok(()));
+        assert_eq!(vec.push_within_capacity(2u32), Ok(()));
+        assert_eq!(vec.push_within_capacity(3u32), Ok(()));
+        assert_eq!(vec.len(), 3);
+        assert_eq!(vec.data.as_ptr(), data);
+
+        assert_eq!(vec.push_within_capacity(4u8), Err(4));
+
+        // Raising the alignment of the buffer may reallocate it.
+        let mut vec = AnyVec::with_capacity(4, 4);
+        assert_eq!(vec.push_within_capacity(1u32), Err(1));
+        assert_eq!(vec.push_within_capacity(1u8), Ok(()));
+    }
+
+    #[test]
+    fn capacity_elements() {
+        let vec = AnyVec::with_capacity(12, 4);
+        assert_eq!(vec.capacity_elements(), vec.meta.capacity());
+        assert_eq!(vec.capacity_elements(), 12);
+        assert_eq!(AnyVec::new().capacity_elements(), MetaBuf::<4>::new().capacity());
+    }
+
+    #[test]
+    fn capacity_plan() {
+        let vec = CapacityPlan::new()
+            .add::<u64>(4)
+            .add::<u8>(3)
+            .add::<TestData>(2)
+            .build();
+        // The `TestData` elements start at the next multiple of 8 after the bytes.
+        assert_eq!(vec.data.capacity(), 40 + 2 * mem::size_of::<TestData>());
+        assert_eq!(vec.meta.capacity(), 9);
+    }
+
+    #[test]
+    fn extend() {
+        let mut vec = AnyVec::new();
+        vec.push("start");
+        vec.extend(0..5i32);
+        vec.extend((5..10i32).filter(|x| x % 2 == 0));
+        assert_eq!(vec.len(), 8);
+        assert_eq!(vec.slice::<i32>(1..8).unwrap(), [0, 1, 2, 3, 4, 6, 8]);
+    }
+
+    #[test]
+    fn from_slice() {
+        let vec = AnyVec::from_slice(&[1u64, 2, 3]);
+        assert_eq!(vec.len(), 3);
+        assert_eq!(vec.slice::<u64>(0..3).unwrap(), &[1, 2, 3]);
+        assert_eq!(vec.data.capacity(), vec.data_len());
+        assert!(AnyVec::from_slice::<u8>(&[]).is_empty());
+    }
+
+    #[test]
+    fn from_default() {
+        let vec = AnyVec::from_default::<u32>(10);
+        assert_eq!(vec.len(), 10);
+        assert_eq!(vec.data.capacity(), 40);
+        for i in 0..10 {
+            assert_eq!(*vec.get::<u32>(i).unwrap().unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn reserve() {
+        let mut vec = AnyVec::new();
+        vec.reserve(8, 64);
+        assert!(vec.capacity(64) >= 8);
+        let mut vec = AnyVec::new();
+        vec.reserve(8, 64);
+        assert!(vec.capacity(32) >= 8);
+        let mut vec = AnyVec::new();
+        vec.reserve(16, 64);
+        assert!(vec.capacity(64) >= 16);
+        let mut vec = AnyVec::new();
+        vec.reserve(16, 32);
+        assert!(vec.capacity(64) >= 8);
+        let mut vec = AnyVec::new();
+        vec.reserve(8, 20);
+        assert!(vec.capacity(16) >= 8);
+        let mut vec = AnyVec::new();
+        vec.reserve(8, 16);
+        assert!(vec.capacity(20) >= 6);
+    }
+
+    #[test]
+    fn reserve_exact() {
+        let mut vec = AnyVec::new();
+        vec.reserve_exact(8, 64);
+        assert!(vec.capacity(64) >= 8);
+        let mut vec = AnyVec::new();
+        vec.reserve_exact(8, 64);
+        assert!(vec.capacity(32) >= 8);
+        let mut vec = AnyVec::new();
+        vec.reserve_exact(16, 64);
+        assert!(vec.capacity(64) >= 16);
+        let mut vec = AnyVec::new();
+        vec.reserve_exact(16, 32);
+        assert!(vec.capacity(64) >= 8);
+        let mut vec = AnyVec::new();
+        vec.reserve_exact(8, 20);
+        assert!(vec.capacity(16) >= 8);
+        let mut vec = AnyVec::new();
+        vec.reserve_exact(8, 16);
+        assert!(vec.capacity(20) >= 6);
+    }
+
+    #[test]
+    fn try_reserve() {
+        let mut vec = AnyVec::new();
+        vec.try_reserve(8, 64).unwrap();
+        assert!(vec.capacity(64) >= 8);
+
+        vec.push(0u8);
+        assert_eq!(vec.try_reserve(usize::MAX / 2, 16), Err(capacity_overflow()));
+        assert!(vec.try_reserve(usize::MAX, 1).is_err());
+        assert!(vec.try_reserve(1, usize::MAX).is_err());
+        assert!(vec.try_reserve(usize::MAX, 0).is_err());
+        assert_eq!(vec.len(), 1);
+        assert!(vec.capacity(64) >= 8);
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut vec = AnyVec::with_capacity(4, 1);
+        vec.push(0u8);
+        vec.push(1u8);
+        vec.shrink_to_fit();
+        assert_eq!(vec.capacity(1), 2);
+
+        let mut vec = AnyVec::with_capacity(8, 2);
+        vec.push(0u16);
+        vec.push(1u16);
+        vec.push(2u16);
+        vec.shrink_to_fit();
+        assert_eq!(vec.capacity(2), 3);
+
+        let mut vec = AnyVec::with_capacity(8, mem::size_of::<TestData>());
+        vec.push(TestData { a: 0, b: "Test" });
+        vec.push(TestData { a: 1, b: "Test" });
+        vec.shrink_to_fit();
+        assert_eq!(vec.capacity(mem::size_of::<TestData>()), 2);
+    }
+
+    #[test]
+    fn shrink_to_fit_prime_sizes() {
+        let mut vec = AnyVec::with_capacity(64, 16);
+        for i in 0..7u8 {
+            vec.push([i; 3]);
+        }
+        vec.shrink_to_fit();
+        assert_eq!(vec.data.capacity(), vec.data_len());
+        assert_eq!(vec.capacity(3), 7);
+
+        let mut vec = AnyVec::new();
+        for i in 0..11u8 {
+            vec.push([i; 5]);
+            vec.push([i; 7]);
+            vec.push([i; 13]);
+            vec.push(u32::from(i));
+        }
+        vec.remove(3);
+        vec.shrink_to_fit();
+        assert_eq!(vec.data.capacity(), vec.data_len());
+        assert_eq!(vec.meta.capacity(), vec.len());
+        assert_eq!(vec.get::<[u8; 13]>(41).unwrap().unwrap(), &[10; 13]);
+
+        vec.clear();
+        vec.shrink_to_fit();
+        assert_eq!(vec.data.capacity(), 0);
+    }
+
+    #[test]
+    fn resize() {
+        let mut vec = AnyVec::new();
+        vec.push(0u8);
+        vec.resize(4, String::from("Test"));
+        assert_eq!(vec.len(), 4);
+        assert_eq!(*vec.get::<u8>(0).unwrap().unwrap(), 0);
+        for i in 1..4 {
+            assert_eq!(vec.get::<String>(i).unwrap().unwrap(), "Test");
+        }
+
+        let rc = Rc::new(());
+        vec.resize(6, rc.clone());
+        assert_eq!(Rc::strong_count(&rc), 3);
+        vec.resize(5, rc.clone());
+        assert_eq!(Rc::strong_count(&rc), 2);
+        vec.resize(1, rc.clone());
+        assert_eq!(Rc::strong_count(&rc), 1);
+        assert_eq!(vec.len(), 1);
+    }
+
+    #[test]
+    fn truncate() {
+        let mut vec = AnyVec::new();
+        vec.push(0);
+        vec.push(1);
+        vec.push(2);
+        vec.push(3);
+        vec.truncate(2);
+        assert_eq!(vec.len(), 2);
+
+        let mut vec = AnyVec::new();
+        vec.push(TestData { a: 0, b: "Test" });
+        vec.push(TestData { a: 1, b: "Test" });
+        vec.push(TestData { a: 2, b: "Test" });
+        vec.push(TestData { a: 3, b: "Test" });
+        vec.truncate(3);
+        assert_eq!(vec.len(), 3);
+    }
+
+    #[test]
+    fn insert_default() {
+        let mut vec = AnyVec::new();
+        vec.push(1u32);
+        vec.push(2u32);
+        vec.insert_default::<u32>(1);
+        assert_eq!(vec.len(), 3);
+        assert_eq!(*vec.get::<u32>(1).unwrap().unwrap(), 0);
+        assert_eq!(*vec.get::<u32>(2).unwrap().unwrap(), 2);
+    }
+
+    #[test]
+    fn insert() {
+        let mut vec = AnyVec::new();
+        vec.insert(0, TestData { a: 1, b: "Test" });
+        vec.insert(1, TestData { a: 2, b: "Test" });
+        vec.insert(0, TestData { a: 0, b: "Test" });
+        vec.insert(3, TestData { a: 3, b: "Test" });
+        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
+        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().b, "Test");
+        assert_eq!(vec.get::<TestData>(1).unwrap().unwrap().a, 1);
+        assert_eq!(vec.get::<TestData>(2).unwrap().unwrap().a, 2);
+        assert_eq!(vec.get::<TestData>(3).unwrap().unwrap().a, 3);
+    }
+
+    #[test]
+    fn insert_bytes() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(2u64);
+        let bytes = 0xdead_beef_u32.to_ne_bytes();
+        unsafe {
+            vec.insert_bytes(1, TypeId::of::<u32>(), &bytes, 4, None);
+        }
+        assert_eq!(vec.len(), 3);
+        assert_eq!(*vec.get::<u32>(1).unwrap().unwrap(), 0xdead_beef);
+        assert_eq!(*vec.get::<u64>(2).unwrap().unwrap(), 2);
+        vec.debug_assert_consistent();
+
+        let rc = Rc::new(());
+        let value = ManuallyDrop::new(rc.clone());
+        let bytes = unsafe {
+            slice::from_raw_parts(&*value as *const Rc<()> as *const u8, mem::size_of::<Rc<()>>())
+        };
+        unsafe {
+            vec.insert_bytes(
+                0,
+                TypeId::of::<Rc<()>>(),
+                bytes,
+                mem::align_of::<Rc<()>>(),
+                Some(drop_glue::<Rc<()>>),
+            );
+        }
+        assert!(vec.is::<Rc<()>>(0).unwrap());
+        mem::drop(vec);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn insert_slice() {
+        let mut vec = AnyVec::new();
+        vec.push("Test");
+        vec.push(TestData { a: 4, b: "Test" });
+        vec.insert_slice(1, &[1u16, 2, 3]);
+        vec.insert_slice(0, &[0u16]);
+        vec.insert_slice::<u16>(6, &[]);
+
+        assert_eq!(vec.len(), 6);
+        assert!(vec.is::<&str>(1).unwrap());
+        assert_eq!(*vec.get::<u16>(0).unwrap().unwrap(), 0);
+        assert_eq!(*vec.get::<u16>(2).unwrap().unwrap(), 1);
+        assert_eq!(*vec.get::<u16>(3).unwrap().unwrap(), 2);
+        assert_eq!(*vec.get::<u16>(4).unwrap().unwrap(), 3);
+        assert_eq!(vec.get::<TestData>(5).unwrap().unwrap().a, 4);
+    }
+
+    #[test]
+    fn remove_and_return() {
+        let mut vec = AnyVec::new();
+        vec.insert(0, TestData { a: 1, b: "Test" });
+        vec.insert(1, TestData { a: 2, b: "Test" });
+        vec.insert(0, TestData { a: 0, b: "Test" });
+        vec.insert(3, TestData { a: 3, b: "Test" });
+
+        assert_eq!(vec.remove_and_return::<TestData>(2).unwrap().a, 2);
+        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
+        assert_eq!(vec.remove_and_return::<TestData>(1).unwrap().a, 1);
+        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
+        assert_eq!(vec.remove_and_return::<TestData>(0).unwrap().a, 0);
+        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 3);
+    }
+
+    #[test]
+    fn remove() {
+        let mut vec = AnyVec::new();
+        vec.insert(0, TestData { a: 1, b: "Test" });
+        vec.insert(1, TestData { a: 2, b: "Test" });
+        vec.insert(0, TestData { a: 0, b: "Test" });
+        vec.insert(3, TestData { a: 3, b: "Test" });
+
+        vec.remove(2);
+        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
+        vec.remove(1);
+        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
+        vec.remove(0);
+        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 3);
+    }
+
+    #[test]
+    fn is() {
+        let mut vec = AnyVec::new();
+        vec.push(TestData { a: 0, b: "Test" });
+        vec.push("Test");
+        vec.push(0u8);
+
+        assert!(vec.is::<TestData>(0).unwrap());
+        assert!(vec.is::<&str>(1).unwrap());
+        assert!(!vec.is::<TestData>(1).unwrap());
+        assert!(vec.is::<u8>(2).unwrap());
+    }
+
+    #[test]
+    fn is_aligned_at() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(2u64);
+        vec.insert(1, 3u8);
+
+        assert_eq!(vec.is_aligned_at(0), Some(true));
+        assert_eq!(vec.is_aligned_at(1), Some(true));
+        assert_eq!(vec.is_aligned_at(2), Some(true));
+        assert_eq!(vec.is_aligned_at(3), None);
+    }
+
+    #[test]
+    fn get() {
+        let mut vec = AnyVec::new();
+        vec.push(TestData { a: 0, b: "Test" });
+        vec.push(TestData { a: 0, b: "Test" });
+        vec.push(TestData { a: 0, b: "Test" });
+        vec.push(TestData { a: 0, b: "Test" });
+
+        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
+        vec.get_mut::<TestData>(0).unwrap().unwrap().a += 1;
+        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 1);
+        assert_eq!(vec.get::<TestData>(2).unwrap().unwrap().a, 0);
+    }
+
+    #[test]
+    fn lock_type() {
+        let mut vec = AnyVec::new();
+        vec.lock_type::<u32>();
+        assert_eq!(vec.try_push(1u32), Ok(()));
+        let err = vec.try_push("Test").unwrap_err();
+        assert_eq!(
+            err,
+            AnyVecError::TypeMismatch {
+                expected: TypeId::of::<&str>(),
+                found: TypeId::of::<u32>(),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "invalid type {:?}, expected {:?}",
+                TypeId::of::<&str>(),
+                TypeId::of::<u32>()
+            )
+        );
+        assert!(vec.try_insert(0, 2u64).is_err());
+        assert_eq!(vec.len(), 1);
+        assert_eq!(vec.data_len(), 4);
+
+        vec.unlock_type();
+        assert_eq!(vec.try_push("Test"), Ok(()));
+        assert_eq!(vec.len(), 2);
+    }
+
+    #[test]
+    fn max_element_size() {
+        let mut vec = AnyVec::new();
+        vec.set_max_element_size(8);
+        assert_eq!(vec.try_push(1u64), Ok(()));
+        assert_eq!(vec.try_push([0u8; 8]), Ok(()));
+        assert_eq!(
+            vec.try_push([0u32; 16]),
+            Err(AnyVecError::TooLarge { size: 64, max: 8 })
+        );
+        assert!(vec.try_insert(0, TestData { a: 0, b: "Test" }).is_err());
+        assert_eq!(vec.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds the maximum size")]
+    fn max_element_size_push() {
+        let mut vec = AnyVec::new();
+        vec.set_max_element_size(4);
+        vec.push(1u64);
+    }
+
+    #[test]
+    fn remove_indices() {
+        let rc = Rc::new(());
+        let mut vec = AnyVec::new();
+        for i in 0..6u8 {
+            vec.push(i);
+            vec.push(rc.clone());
+            vec.push(u64::from(i));
+        }
+
+        let removed = vec.remove_indices(&[17, 0, 4, 4, 9]);
+        assert_eq!(removed.len(), 4);
+        assert_eq!(vec.len(), 14);
+        assert_eq!(*removed.get::<u8>(0).unwrap().unwrap(), 0);
+        assert!(removed.is::<Rc<()>>(1).unwrap());
+        assert_eq!(*removed.get::<u8>(2).unwrap().unwrap(), 3);
+        assert_eq!(*removed.get::<u64>(3).unwrap().unwrap(), 5);
+        assert!(vec.is::<Rc<()>>(0).unwrap());
+        assert_eq!(*vec.get::<u64>(1).unwrap().unwrap(), 0);
+        assert_eq!(*vec.get::<u8>(2).unwrap().unwrap(), 1);
+        assert_eq!(*vec.get::<u64>(3).unwrap().unwrap(), 1);
+        vec.debug_assert_consistent();
+        removed.debug_assert_consistent();
+
+        mem::drop(removed);
+        assert_eq!(Rc::strong_count(&rc), 6);
+        mem::drop(vec);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn remove_indices_out_of_bounds() {
+        let mut vec = AnyVec::new();
+        vec.push(0u8);
+        vec.remove_indices(&[0, 1]);
+    }
+
+    #[test]
+    fn extract_if() {
+        let mut vec = AnyVec::new();
+        for (i, x) in (0..6i32).enumerate() {
+            if i % 2 == 0 {
+                vec.push("Test");
+            }
+            vec.push(x);
+        }
+
+        let mut odd = vec.extract_if(|x: &i32| x % 2 != 0);
+        assert_eq!(odd.len(), 3);
+        for x in &[1, 3, 5] {
+            assert_eq!(odd.remove_and_return::<i32>(0), Ok(*x));
+        }
+
+        assert_eq!(vec.len(), 6);
+        for (i, x) in [0, 2, 4].iter().enumerate() {
+            assert_eq!(*vec.get::<&str>(2 * i).unwrap().unwrap(), "Test");
+            assert_eq!(*vec.get::<i32>(2 * i + 1).unwrap().unwrap(), *x);
+        }
+    }
+
+    #[test]
+    fn cursor_mut() {
+        let mut vec = AnyVec::new();
+        vec.extend(0..6u32);
+        vec.insert(3, "Test");
+
+        let mut cursor = vec.cursor_mut();
+        loop {
+            match cursor.current::<u32>() {
+                Ok(Some(x)) if x % 2 == 0 => cursor.remove(),
+                Ok(Some(_)) => {
+                    *cursor.current_mut::<u32>().unwrap().unwrap() *= 10;
+                    cursor.move_next();
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    cursor.move_next();
+                }
+            }
+        }
+        assert_eq!(cursor.index(), 4);
+        assert!(!cursor.move_next());
+
+        assert_eq!(vec.len(), 4);
+        assert_eq!(vec.collect_type::<u32>(), [&10, &30, &50]);
+        assert_eq!(*vec.get::<&str>(1).unwrap().unwrap(), "Test");
+    }
+
+    #[test]
+    fn from_raw_parts() {
+        let mut vec = AnyVec::new();
+        vec.push(1u64);
+        vec.push(2u32);
+        vec.push(3u16);
+        vec.push(4u8);
+
+        // The elements leave no padding, so all bytes are initialized.
+        let bytes = vec
+            .as_bytes()
+            .iter()
+            .map(|byte| unsafe { byte.assume_init() })
+            .collect();
+        let meta = vec.meta_entries().collect::<Vec<_>>();
+        assert_eq!(meta[1], (TypeId::of::<u32>(), 8, 4));
+
+        let mut copy = unsafe { AnyVec::from_raw_parts(bytes, meta) };
+        assert_eq!(copy.len(), 4);
+        assert_eq!(copy.data_len(), 15);
+        assert_eq!(*copy.get::<u64>(0).unwrap().unwrap(), 1);
+        assert_eq!(*copy.get::<u32>(1).unwrap().unwrap(), 2);
+        assert_eq!(*copy.get::<u16>(2).unwrap().unwrap(), 3);
+        assert_eq!(*copy.get::<u8>(3).unwrap().unwrap(), 4);
+
+        copy.insert(0, 5u8);
+        assert_eq!(*copy.get::<u64>(1).unwrap().unwrap(), 1);
+    }
+
+    #[test]
+    fn try_split_off() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(2u64);
+
+        let other = vec.try_split_off(2).unwrap();
+        assert!(other.is_empty());
+        assert_eq!(vec.len(), 2);
+
+        assert_eq!(
+            vec.try_split_off(3).err(),
+            Some(AnyVecError::OutOfBounds { index: 3, len: 2 })
+        );
+        assert_eq!(vec.len(), 2);
+        assert_eq!(vec.data_len(), 16);
+
+        let other = vec.try_split_off(0).unwrap();
+        assert!(vec.is_empty());
+        assert_eq!(vec.data_len(), 0);
+        assert_eq!(other.len(), 2);
+        assert_eq!(*other.get::<u8>(0).unwrap().unwrap(), 1);
+        assert_eq!(*other.get::<u64>(1).unwrap().unwrap(), 2);
+    }
+
+    #[test]
+    fn iter_dyn() {
+        trait Name {
+            fn name(&self) -> String;
+        }
+        impl Name for u32 {
+            fn name(&self) -> String {
+                format!("u32 {}", self)
+            }
+        }
+        impl<'a> Name for TestData<'a> {
+            fn name(&self) -> String {
+                self.b.to_string()
+            }
+        }
+
+        let mut vec = AnyVec::new();
+        vec.push_dyn(7u32, |x| x as &dyn Name);
+        vec.push(1u8);
+        vec.push_dyn(TestData { a: 0, b: "Test" }, |x| x as &dyn Name);
+        vec.push_dyn(8u32, |x| x as &dyn fmt::Debug);
+
+        let names = vec
+            .iter_dyn::<dyn Name>()
+            .map(|x| x.map(|x| x.name()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![Some("u32 7".to_string()), None, Some("Test".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn savings_vs_boxed() {
+        let mut vec = AnyVec::new();
+        assert_eq!(vec.savings_vs_boxed(), 0);
+        for i in 0..100u8 {
+            vec.push(i);
+        }
+        assert_eq!(
+            vec.savings_vs_boxed(),
+            100 * 2 * mem::size_of::<usize>() as isize
+        );
+    }
+
+    #[test]
+    fn copy_within() {
+        let mut vec = AnyVec::new();
+        for x in 0..6u16 {
+            vec.push(x);
+        }
+        let values = |vec: &AnyVec| {
+            (0..vec.len())
+                .map(|i| *vec.get::<u16>(i).unwrap().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        vec.copy_within::<u16>(0..3, 2).unwrap();
+        assert_eq!(values(&vec), [0, 1, 0, 1, 2, 5]);
+        vec.copy_within::<u16>(3..6, 0).unwrap();
+        assert_eq!(values(&vec), [1, 2, 5, 1, 2, 5]);
+
+        assert_eq!(
+            vec.copy_within::<u16>(0..3, 4),
+            Err(AnyVecError::OutOfBounds { index: 6, len: 6 })
+        );
+        vec.push(6u32);
+        assert_eq!(
+            vec.copy_within::<u16>(4..7, 0),
+            Err(AnyVecError::TypeMismatch {
+                expected: TypeId::of::<u16>(),
+                found: TypeId::of::<u32>(),
+            })
+        );
+        assert_eq!(vec.pop::<u32>(), Ok(Some(6)));
+        assert_eq!(values(&vec), [1, 2, 5, 1, 2, 5]);
+    }
+
+    #[test]
+    fn interleave3() {
+        let vec = AnyVec::interleave3(
+            vec![0u8, 1, 2, 3],
+            vec![0u64, 10, 20, 30],
+            vec!["a", "b", "c", "d"],
+        )
+        .unwrap();
+        assert_eq!(vec.len(), 12);
+        for (i, name) in ["a", "b", "c", "d"].iter().enumerate() {
+            assert_eq!(*vec.get::<u8>(3 * i).unwrap().unwrap(), i as u8);
+            assert_eq!(*vec.get::<u64>(3 * i + 1).unwrap().unwrap(), 10 * i as u64);
+            assert_eq!(vec.get::<&str>(3 * i + 2).unwrap().unwrap(), name);
+        }
+
+        assert_eq!(
+            AnyVec::interleave3(vec![0u8], vec![0u16], Vec::<u32>::new()).err(),
+            Some(AnyVecError::LengthMismatch {
+                expected: 1,
+                found: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn slice() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        for x in 1..5u32 {
+            vec.push(x);
+        }
+        vec.push(2u8);
+
+        assert_eq!(vec.slice::<u32>(1..5).unwrap().iter().sum::<u32>(), 10);
+        assert_eq!(vec.slice::<u32>(2..4), Ok(&[2, 3][..]));
+        assert_eq!(vec.slice::<u32>(3..3), Ok(&[][..]));
+        assert!(vec.slice::<u32>(0..2).is_err());
+        assert_eq!(
+            vec.slice::<u32>(4..7),
+            Err(AnyVecError::TypeMismatch {
+                expected: TypeId::of::<u32>(),
+                found: TypeId::of::<u8>(),
+            })
+        );
+
+        // With a `u64` in the tail, it only moves by multiples of 8, leaving a gap of 4 bytes.
+        assert_eq!(vec.pop::<u8>(), Ok(Some(2)));
+        vec.insert(2, 0u32);
+        vec.push(0u64);
+        vec.remove(2);
+        assert_eq!(
+            vec.slice::<u32>(1..5),
+            Err(AnyVecError::NotContiguous { start: 1, end: 5 })
+        );
+        vec.compact();
+        assert_eq!(vec.slice::<u32>(1..5).unwrap(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn slice_mut() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        for x in [4u32, 2, 3, 1] {
+            vec.push(x);
+        }
+        vec.push(2u8);
+
+        vec.slice_mut::<u32>(1..5).unwrap().sort();
+        assert_eq!(vec.slice::<u32>(1..5).unwrap(), [1, 2, 3, 4]);
+        assert_eq!(vec.slice_mut::<u32>(3..3), Ok(&mut [][..]));
+        assert_eq!(
+            vec.slice_mut::<u32>(4..6),
+            Err(AnyVecError::TypeMismatch {
+                expected: TypeId::of::<u32>(),
+                found: TypeId::of::<u8>(),
+            })
+        );
+    }
+
+    #[test]
+    fn truncate_counted() {
+        let counter = Rc::new(());
+        let mut vec = AnyVec::new();
+        for _ in 0..5 {
+            vec.push(counter.clone());
+        }
+        assert_eq!(vec.truncate_counted(2), 3);
+        assert_eq!(Rc::strong_count(&counter), 3);
+        assert_eq!(vec.truncate_counted(2), 0);
+        assert_eq!(vec.truncate_counted(3), 0);
+        assert_eq!(vec.len(), 2);
+    }
+
+    #[test]
+    fn prepend() {
+        let mut vec = AnyVec::new();
+        vec.push(3u8);
+        vec.push(TestData { a: 4, b: "Test" });
+
+        let mut other = AnyVec::new();
+        other.push(1u64);
+        other.push(2u8);
+        vec.prepend(&mut other);
+        assert!(other.is_empty());
+        assert_eq!(other.data_len(), 0);
+
+        assert_eq!(vec.len(), 4);
+        assert_eq!(*vec.get::<u64>(0).unwrap().unwrap(), 1);
+        assert_eq!(*vec.get::<u8>(1).unwrap().unwrap(), 2);
+        assert_eq!(*vec.get::<u8>(2).unwrap().unwrap(), 3);
+        assert_eq!(vec.get::<TestData>(3).unwrap().unwrap().a, 4);
+    }
+
+    #[test]
+    fn align_at() {
+        let mut vec = AnyVec::new();
+        vec.push(1u64);
+        vec.push(2u8);
+        assert_eq!(vec.align_at(0), Some(8));
+        assert_eq!(vec.align_at(1), Some(1));
+        assert_eq!(vec.align_at(2), None);
+    }
+
+    #[test]
+    fn pop_any() {
+        let rc = Rc::new(());
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(rc.clone());
+        vec.push(String::from("Test"));
+
+        assert_eq!(*vec.pop_any().unwrap().downcast::<String>().unwrap(), "Test");
+        assert_eq!(vec.pop_any().unwrap().downcast::<Rc<()>>().ok(), Some(Box::new(rc.clone())));
+        assert_eq!(Rc::strong_count(&rc), 1);
+        assert_eq!(*vec.pop_any().unwrap().downcast::<u8>().unwrap(), 1);
+        assert!(vec.pop_any().is_none());
+        assert_eq!(vec.data_len(), 0);
+
+        let mut vec = unsafe { AnyVec::from_raw_parts(vec![1], vec![(TypeId::of::<u8>(), 0, 1)]) };
+        assert!(vec.pop_any().is_none());
+        assert_eq!(vec.len(), 1);
+    }
+
+    #[test]
+    fn swap_remove_any() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(TestData { a: 2, b: "Test" });
+        vec.push(3u32);
+        vec.push(4u16);
+
+        let element = vec.swap_remove_any(1).unwrap();
+        assert_eq!(element.downcast_ref::<TestData>().unwrap().a, 2);
+        assert_eq!(vec.len(), 3);
+        assert_eq!(*vec.get::<u8>(0).unwrap().unwrap(), 1);
+        assert_eq!(*vec.get::<u16>(1).unwrap().unwrap(), 4);
+        assert_eq!(*vec.get::<u32>(2).unwrap().unwrap(), 3);
+        assert_eq!(vec.data_len(), 8);
+
+        let element = vec.swap_remove_any(2).unwrap();
+        assert_eq!(*element.downcast::<u32>().unwrap(), 3);
+        assert_eq!(vec.data_len(), 4);
+        assert!(vec.swap_remove_any(2).is_none());
+    }
+
+    #[test]
+    fn content_cmp() {
+        let mut a = AnyVec::new();
+        a.push_ord(1i32);
+        a.push_ord(2i32);
+        let mut b = AnyVec::new();
+        b.push_ord(1i32);
+        b.push_ord(3i32);
+        assert_eq!(a.content_cmp(&b), Some(Ordering::Less));
+        assert_eq!(b.content_cmp(&a), Some(Ordering::Greater));
+        assert_eq!(a.content_cmp(&a), Some(Ordering::Equal));
+
+        b.pop::<i32>().unwrap();
+        assert_eq!(a.content_cmp(&b), Some(Ordering::Greater));
+        b.push_ord(2u32);
+        assert_eq!(a.content_cmp(&b), None);
+
+        let mut c = AnyVec::new();
+        c.push(1i32);
+        assert_eq!(c.content_cmp(&c), None);
+    }
+
+    #[test]
+    fn clear_and_shrink() {
+        let mut vec = AnyVec::new();
+        for i in 0..10u64 {
+            vec.push(i);
+        }
+        let capacity = vec.capacity(8);
+        assert!(capacity >= 10);
+
+        vec.clear();
+        assert!(vec.is_empty());
+        assert_eq!(vec.capacity(8), capacity);
+
+        vec.clear_and_shrink();
+        assert_eq!(vec.capacity(8), 0);
+        assert_eq!(vec.data.buf.capacity(), 0);
+        assert_eq!(vec.meta.capacity(), MetaBuf::<4>::new().capacity());
+        vec.debug_assert_consistent();
+    }
+
+    #[test]
+    #[cfg(feature = "smallvec")]
+    fn inline_meta() {
+        let mut vec = AnyVecN::<8>::new();
+        vec.extend(0..8u8);
+        assert!(!vec.meta.spilled());
+        vec.push(8u8);
+        assert!(vec.meta.spilled());
+        assert_eq!(vec.split_off(4).meta.inline_size(), 8);
+    }
+
+    #[test]
+    fn drain_all() {
+        let counter = Rc::new(());
+        let mut vec = AnyVec::new();
+        for i in 0..4 {
+            vec.push(i);
+            vec.push(counter.clone());
+        }
+        let capacity = vec.capacity(1);
+        let capacity_elements = vec.capacity_elements();
+
+        {
+            let mut drain = vec.drain_all();
+            assert_eq!(*drain.next().unwrap().downcast::<i32>().unwrap(), 0);
+            let rc = drain.next().unwrap().downcast::<Rc<()>>().unwrap();
+            assert!(Rc::ptr_eq(&rc, &counter));
+            assert_eq!(*drain.next().unwrap().downcast::<i32>().unwrap(), 1);
+            assert!(drain.next().unwrap().is::<Rc<()>>());
+        }
+        assert!(vec.is_empty());
+        assert_eq!(vec.data_len(), 0);
+        assert_eq!(Rc::strong_count(&counter), 1);
+        assert_eq!(vec.capacity(1), capacity);
+        assert_eq!(vec.capacity_elements(), capacity_elements);
+
+        vec.push(5u8);
+        assert_eq!(vec.drain_all().count(), 1);
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    fn get_or_bytes() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(0x0102_0304u32);
+
+        assert_eq!(vec.get_or_bytes::<u8>(0).ok(), Some(&1));
+        let (type_id, bytes) = vec.get_or_bytes::<u8>(1).unwrap_err();
+        assert_eq!(type_id, TypeId::of::<u32>());
+        assert_eq!(bytes.len(), 4);
+        assert_eq!(unsafe { assume_init(bytes) }, 0x0102_0304u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn iter_cloned_any() {
+        let mut vec = AnyVec::new();
+        vec.push_cloneable(String::from("Test"));
+        vec.push(1u8);
+        vec.push_cloneable(2u64);
+
+        let boxes = vec.iter_cloned_any().collect::<Vec<_>>();
+        assert_eq!(boxes.len(), 2);
+        assert_eq!(boxes[0].downcast_ref::<String>().unwrap(), "Test");
+        assert_eq!(boxes[1].downcast_ref::<u64>(), Some(&2));
+        assert_eq!(vec.get::<String>(0).unwrap().unwrap(), "Test");
+    }
+
+    #[test]
+    fn reserve_data_and_meta() {
+        let mut vec = AnyVec::new();
+        vec.reserve_data(100);
+        assert!(vec.data.capacity() >= 100);
+        assert_eq!(vec.meta.capacity(), MetaBuf::<4>::new().capacity());
+
+        let mut vec = AnyVec::new();
+        vec.reserve_meta(10);
+        assert!(vec.meta.capacity() >= 10);
+        assert_eq!(vec.data.capacity(), 0);
+    }
+
+    #[test]
+    fn reserve_for() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.reserve_for::<u64>(100);
+        let data = vec.data.as_ptr();
+        let meta = vec.meta.as_ptr();
+        for i in 0..100u64 {
+            vec.push(i);
+        }
+        assert_eq!(vec.data.as_ptr(), data);
+        assert_eq!(vec.meta.as_ptr(), meta);
+    }
+
+    #[test]
+    fn growth() {
+        let mut vec = AnyVec::new();
+        vec.set_growth(Growth::Exact);
+        vec.push(1u8);
+        vec.push(2u32);
+        vec.push([3u16; 5]);
+        vec.push(4u64);
+        assert_eq!(vec.data.capacity(), vec.data.len());
+
+        let mut vec = AnyVec::new();
+        vec.set_growth(Growth::Fixed(64));
+        vec.push(1u8);
+        assert_eq!(vec.data.capacity(), 64);
+        vec.push([2u8; 100]);
+        assert_eq!(vec.data.capacity(), 101);
+    }
+
+    #[test]
+    fn align_for() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.align_for::<u64>();
+        vec.debug_assert_consistent();
+        assert_eq!(vec.data_len(), 1);
+        assert!(vec.data.align() >= 8);
+
+        let data = vec.data.as_ptr();
+        vec.push(2u64);
+        vec.debug_assert_consistent();
+        assert_eq!(vec.data.as_ptr(), data);
+        assert_eq!(vec.data_len(), 16);
+        assert_eq!(*vec.get::<u64>(1).unwrap().unwrap(), 2);
+    }
+
+    #[test]
+    fn reserve_for_append() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        let mut other = AnyVec::new();
+        for i in 0..100u64 {
+            other.push(i);
+        }
+        vec.reserve_for_append(&other);
+        let data = vec.data.as_ptr();
+        let meta = vec.meta.as_ptr();
+        let capacity = vec.capacity(1);
+        let capacity_elements = vec.capacity_elements();
+
+        vec.append(&mut other);
+        assert_eq!(vec.len(), 101);
+        assert_eq!(*vec.get::<u64>(100).unwrap().unwrap(), 99);
+        assert_eq!(vec.data.as_ptr(), data);
+        assert_eq!(vec.meta.as_ptr(), meta);
+        assert_eq!(vec.capacity(1), capacity);
+        assert_eq!(vec.capacity_elements(), capacity_elements);
+    }
+
+    #[test]
+    fn append_empty() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        let mut other = AnyVec::new();
+        other.push(2u64);
+        other.clear();
+
+        vec.reserve_for_append(&other);
+        vec.append(&mut other);
+        assert_eq!(vec.data_len(), 1);
+        vec.debug_assert_consistent();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "out of bounds")]
+    fn from_raw_parts_overflow() {
+        unsafe { AnyVec::from_raw_parts(vec![0; 8], vec![(TypeId::of::<u8>(), usize::MAX, 2)]) };
+    }
+
+    #[test]
+    #[should_panic(expected = "data index out of range")]
+    fn from_raw_parts_unordered() {
+        // The second element is stored before the first, against the contract of
+        // `from_raw_parts`. Moving both to make room used to wrap its data index around.
+        let meta = vec![(TypeId::of::<u8>(), 1, 1), (TypeId::of::<u8>(), 0, 1)];
+        let mut vec = unsafe { AnyVec::from_raw_parts(vec![1, 2], meta) };
+        vec.insert(0, 3u16);
+    }
+
+    #[test]
+    fn push_boxed() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push_boxed(Box::new(String::from("x")));
+        assert_eq!(vec.len(), 2);
+        assert_eq!(vec.get::<String>(1).unwrap().unwrap(), "x");
+        assert_eq!(vec.pop::<String>().unwrap().unwrap(), "x");
+    }
+
+    #[test]
+    fn push_or_get_last_mut() {
+        let mut vec = AnyVec::new();
+        for word in ["a", "b"] {
+            vec.push_or_get_last_mut(String::new).push_str(word);
+        }
+        vec.push(1u8);
+        for word in ["c", "d", "e"] {
+            vec.push_or_get_last_mut(String::new).push_str(word);
+        }
+        *vec.push_or_get_last_mut(|| 0u8) += 1;
+
+        assert_eq!(vec.len(), 4);
+        assert_eq!(vec.collect_type::<String>(), [&"ab", &"cde"]);
+        assert_eq!(*vec.get::<u8>(3).unwrap().unwrap(), 1);
+    }
+
+    #[test]
+    fn emplace() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        unsafe {
+            vec.emplace(|slot: *mut [u64; 4096]| {
+                let first = slot as *mut u64;
+                for i in 0..4096 {
+                    first.add(i).write(i as u64 * 2);
+                }
+            });
+        }
+        assert_eq!(vec.len(), 2);
+        assert_eq!(vec.get::<[u64; 4096]>(1).unwrap().unwrap()[100], 200);
+        assert_eq!(vec.is_aligned_at(1), Some(true));
+    }
+
+    #[test]
+    fn emplace_panic() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            vec.emplace(|_: *mut u64| panic!("Test"));
+        }));
+        assert!(result.is_err());
+        assert_eq!(vec.len(), 1);
+        assert_eq!(vec.data_len(), 1);
+        vec.debug_assert_consistent();
+    }
 
-        for meta in other_meta.iter_mut() {
-            meta.data_index -= self.data.len();
+    #[test]
+    fn from_boxed_iter() {
+        let counter = Rc::new(());
+        let values: Vec<Box<dyn Any>> =
+            vec![Box::new(1u8), Box::new("x"), Box::new(counter.clone())];
+        let mut vec = AnyVec::from_boxed_iter(values);
+        assert_eq!(vec.len(), 3);
+        assert_eq!(*vec.get::<u8>(0).unwrap().unwrap(), 1);
+        assert_eq!(*vec.get::<&str>(1).unwrap().unwrap(), "x");
+        assert_eq!(Rc::strong_count(&counter), 2);
+
+        vec.remove(0);
+        assert_eq!(*vec.get::<&str>(0).unwrap().unwrap(), "x");
+        let element = vec.swap_remove_any(0).unwrap();
+        assert_eq!(*element.downcast::<&str>().unwrap(), "x");
+        mem::drop(vec);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn layout_order() {
+        let mut vec = AnyVec::new();
+        assert!(vec.layout_order().is_empty());
+        vec.push(1u8);
+        vec.push(2u64);
+        vec.insert(0, 3u16);
+        vec.insert(2, String::from("Test"));
+        vec.remove(1);
+        vec.compact();
+        assert_eq!(vec.layout_order(), vec![0, 1, 2]);
+
+        // Out of order on purpose, which breaks the contract of `from_raw_parts`. The
+        // elements are bytes without drop glue, and only their metadata is inspected.
+        let meta = [2, 0, 3, 1].iter().map(|&i| (TypeId::of::<u8>(), i, 1)).collect();
+        let vec = unsafe { AnyVec::from_raw_parts(vec![10, 20, 30, 40], meta) };
+        assert_eq!(vec.layout_order(), vec![1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn debug_assert_consistent() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(2u64);
+        vec.insert(1, 3u16);
+        vec.remove(0);
+        vec.debug_assert_consistent();
+        vec.prepend(&mut AnyVec::from_default::<u32>(3));
+        vec.debug_assert_consistent();
+        vec.split_off(2).debug_assert_consistent();
+        vec.debug_assert_consistent();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "element 1 overlaps the element before it")]
+    fn debug_assert_consistent_overlap() {
+        let meta = vec![(TypeId::of::<u32>(), 0, 4), (TypeId::of::<u16>(), 2, 2)];
+        let vec = unsafe { AnyVec::from_raw_parts(vec![0; 4], meta) };
+        vec.debug_assert_consistent();
+    }
+
+    #[test]
+    fn collect_type() {
+        let mut vec = AnyVec::new();
+        vec.push("a");
+        vec.push(1u32);
+        vec.push("b");
+        vec.push(2u32);
+        vec.push("c");
+        assert_eq!(vec.collect_type::<&str>(), vec![&"a", &"b", &"c"]);
+        assert_eq!(vec.iter_type::<u32>().sum::<u32>(), 3);
+        assert!(vec.collect_type::<u8>().is_empty());
+    }
+
+    #[test]
+    fn clone_type() {
+        let mut vec = AnyVec::new();
+        vec.push(String::from("a"));
+        vec.push(1u32);
+        vec.push(String::from("b"));
+
+        let mut strings = vec.clone_type::<String>();
+        assert_eq!(strings.len(), 2);
+        strings.get_mut::<String>(0).unwrap().unwrap().push('!');
+        assert_eq!(strings.collect_type::<String>(), [&"a!", &"b"]);
+        assert_eq!(vec.collect_type::<String>(), [&"a", &"b"]);
+        assert_eq!(vec.len(), 3);
+        assert!(vec.clone_type::<u8>().is_empty());
+    }
+
+    #[test]
+    fn iter_type_by() {
+        let mut vec = AnyVec::new();
+        vec.extend(0..5u32);
+        let order: Vec<usize> = (0..5).rev().collect();
+        let values: Vec<u32> = vec.iter_type_by::<u32>(&order).copied().collect();
+        assert_eq!(values, [4, 3, 2, 1, 0]);
+
+        vec.push("Test");
+        let values: Vec<u32> = vec.iter_type_by::<u32>(&[5, 2, 9, 2]).copied().collect();
+        assert_eq!(values, [2, 2]);
+    }
+
+    #[test]
+    fn iter_types() {
+        let mut vec = AnyVec::new();
+        vec.push(1u32);
+        vec.push("a");
+        vec.push(2.5f32);
+        vec.push(3u32);
+        vec.push("b");
+
+        let items: Vec<_> = vec.iter_types::<u32, f32>().collect();
+        assert_eq!(items, [Either::Left(&1), Either::Right(&2.5), Either::Left(&3)]);
+        assert_eq!(vec.iter_types::<u8, u16>().count(), 0);
+    }
+
+    #[test]
+    fn find_all() {
+        let mut vec = AnyVec::new();
+        vec.push(7u8);
+        vec.push(7u32);
+        vec.push(1u8);
+        vec.push("7");
+        vec.push(7u8);
+        assert_eq!(vec.find_all(&7u8), [0, 4]);
+        assert_eq!(vec.find_all(&1u8), [2]);
+        assert!(vec.find_all(&7u64).is_empty());
+    }
+
+    #[test]
+    fn fold_type() {
+        let mut vec = AnyVec::new();
+        for x in 1..5i32 {
+            vec.push(x);
+            vec.push("Test");
         }
+        assert_eq!(vec.fold_type(0, |sum, x: &i32| sum + x), 10);
+        assert_eq!(vec.fold_type(0, |count, _: &&str| count + 1), 4);
+        assert_eq!(vec.fold_type(7, |sum, x: &u8| sum + x), 7);
+    }
 
-        AnyVec {
-            data: other_data,
-            meta: other_meta,
+    #[test]
+    fn set_zeroize() {
+        let read = |vec: &AnyVec, data_index: usize| unsafe {
+            ptr::read(vec.data.as_ptr().add(data_index) as *const u64)
+        };
+        let mut vec = AnyVec::new();
+        vec.set_zeroize(true);
+        vec.push(u64::MAX);
+        vec.push(1u64);
+        vec.push(2u64);
+
+        vec.remove(0);
+        assert_eq!(vec.data_len(), 16);
+        assert_eq!(read(&vec, 0), 1);
+        assert_eq!(read(&vec, 16), 0);
+
+        vec.truncate(0);
+        assert_eq!(read(&vec, 0), 0);
+        assert_eq!(read(&vec, 8), 0);
+
+        vec.set_zeroize(false);
+        vec.push(3u64);
+        vec.remove(0);
+        assert_eq!(read(&vec, 0), 3);
+    }
+
+    #[test]
+    fn set_zeroize_split_off() {
+        let read = |vec: &AnyVec, data_index: usize| unsafe {
+            ptr::read(vec.data.as_ptr().add(data_index) as *const u64)
+        };
+        let mut vec = AnyVec::new();
+        vec.set_zeroize(true);
+        vec.push(1u64);
+        vec.push(u64::MAX);
+
+        let mut other = vec.split_off(1);
+        assert_eq!(vec.data_len(), 8);
+        assert_eq!(read(&vec, 8), 0);
+        assert_eq!(*other.get::<u64>(0).unwrap().unwrap(), u64::MAX);
+
+        vec.append(&mut other);
+        assert_eq!(read(&other, 0), 0);
+        assert_eq!(*vec.get::<u64>(1).unwrap().unwrap(), u64::MAX);
+
+        let mut other = vec.split_off(1);
+        other.prepend(&mut vec);
+        assert_eq!(read(&vec, 0), 0);
+        assert_eq!(other.collect_type::<u64>(), [&1, &u64::MAX]);
+    }
+
+    #[test]
+    fn raw_iter_mut() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(0x0102_0304u32);
+        vec.push(5u16);
+        unsafe {
+            let sizes: Vec<usize> = vec.raw_iter_mut().map(|(_, bytes)| bytes.len()).collect();
+            assert_eq!(sizes, [1, 4, 2]);
+            for (type_id, bytes) in vec.raw_iter_mut() {
+                if type_id == TypeId::of::<u32>() {
+                    bytes.reverse();
+                }
+            }
         }
+        assert_eq!(*vec.get::<u32>(1).unwrap().unwrap(), 0x0403_0201);
+        assert_eq!(*vec.get::<u8>(0).unwrap().unwrap(), 1);
+        assert_eq!(*vec.get::<u16>(2).unwrap().unwrap(), 5);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::mem;
+    #[test]
+    fn iter_cells() {
+        let mut vec = AnyVec::new();
+        for x in 1..4u32 {
+            vec.push(x);
+            vec.push("Test");
+        }
 
-    struct TestData<'a> {
-        a: u64,
-        b: &'a str,
+        {
+            let cells = vec.iter_cells::<u32>().collect::<Vec<_>>();
+            assert_eq!(cells.len(), 3);
+            cells[0].set(cells[1].get() + cells[2].get());
+            cells[2].swap(cells[1]);
+        }
+        assert_eq!(*vec.get::<u32>(0).unwrap().unwrap(), 5);
+        assert_eq!(*vec.get::<u32>(2).unwrap().unwrap(), 3);
+        assert_eq!(*vec.get::<u32>(4).unwrap().unwrap(), 2);
     }
 
     #[test]
-    fn capacity() {
-        assert_eq!(AnyVec::with_capacity(8, 64).capacity(64), 8);
-        assert_eq!(AnyVec::with_capacity(8, 64).capacity(32), 8);
-        assert_eq!(AnyVec::with_capacity(16, 64).capacity(64), 16);
-        assert_eq!(AnyVec::with_capacity(16, 32).capacity(64), 8);
-        assert_eq!(AnyVec::with_capacity(8, 20).capacity(16), 8);
-        assert_eq!(AnyVec::with_capacity(8, 16).capacity(20), 6);
+    fn concat() {
+        let mut a = AnyVec::new();
+        a.push(1u8);
+        let mut b = AnyVec::new();
+        b.push(2u64);
+        b.push("Test");
+        let mut c = AnyVec::new();
+        c.push(3u8);
+        c.push(4u32);
+
+        let vec = AnyVec::concat(vec![a, AnyVec::new(), b, c]);
+        assert_eq!(vec.len(), 5);
+        assert_eq!(*vec.get::<u8>(0).unwrap().unwrap(), 1);
+        assert_eq!(*vec.get::<u64>(1).unwrap().unwrap(), 2);
+        assert_eq!(*vec.get::<&str>(2).unwrap().unwrap(), "Test");
+        assert_eq!(*vec.get::<u8>(3).unwrap().unwrap(), 3);
+        assert_eq!(*vec.get::<u32>(4).unwrap().unwrap(), 4);
+        vec.debug_assert_consistent();
+
+        // The capacity was allocated once: each part's bytes, plus its alignment minus one.
+        assert_eq!(vec.data.capacity(), 1 + (24 + 7) + (8 + 3));
+        assert_eq!(vec.meta.capacity(), 5);
     }
 
     #[test]
-    fn reserve() {
+    fn fill() {
         let mut vec = AnyVec::new();
-        vec.reserve(8, 64);
-        assert!(vec.capacity(64) >= 8);
+        for i in 0..5 {
+            vec.push(i.to_string());
+        }
+        vec.push(5u8);
+
+        vec.fill(1..4, String::from("x")).unwrap();
+        let strings = (0..5)
+            .map(|i| vec.get::<String>(i).unwrap().unwrap().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(strings, ["0", "x", "x", "x", "4"]);
+
+        assert!(vec.fill(3..6, String::new()).is_err());
+        assert_eq!(
+            vec.fill(4..7, String::new()).err(),
+            Some(AnyVecError::TypeMismatch {
+                expected: TypeId::of::<String>(),
+                found: TypeId::of::<u8>(),
+            })
+        );
+        assert_eq!(vec.get::<String>(3).unwrap().unwrap(), "x");
+        assert_eq!(vec.get::<String>(4).unwrap().unwrap(), "4");
+    }
+
+    #[test]
+    fn into_vec() {
         let mut vec = AnyVec::new();
-        vec.reserve(8, 64);
-        assert!(vec.capacity(32) >= 8);
+        for i in 0..3 {
+            vec.push(i.to_string());
+        }
+        assert_eq!(vec.into_vec::<String>().unwrap(), ["0", "1", "2"]);
+
         let mut vec = AnyVec::new();
-        vec.reserve(16, 64);
-        assert!(vec.capacity(64) >= 16);
+        vec.push(String::from("Test"));
+        vec.push(1u8);
+        let (vec, err) = vec.into_vec::<String>().err().unwrap();
+        assert_eq!(
+            err,
+            AnyVecError::TypeMismatch {
+                expected: TypeId::of::<String>(),
+                found: TypeId::of::<u8>(),
+            }
+        );
+        assert_eq!(vec.len(), 2);
+        assert_eq!(vec.get::<String>(0).unwrap().unwrap(), "Test");
+
+        assert!(AnyVec::new().into_vec::<u8>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn into_vec_zeroize() {
         let mut vec = AnyVec::new();
-        vec.reserve(16, 32);
-        assert!(vec.capacity(64) >= 8);
+        vec.set_zeroize(true);
+        vec.push(u64::MAX);
+        vec.push(u64::MAX);
+
+        // `into_vec` frees the buffer, so inspect it after the move it is built on.
+        assert_eq!(unsafe { vec.take_all::<u64>() }, [u64::MAX; 2]);
+        assert!(vec.is_empty());
+        let bytes = unsafe { slice::from_raw_parts(vec.data.as_ptr(), 16) };
+        assert_eq!(bytes, [0; 16]);
+    }
+
+    #[test]
+    fn windows() {
         let mut vec = AnyVec::new();
-        vec.reserve(8, 20);
-        assert!(vec.capacity(16) >= 8);
+        for x in &[1.0f32, 2.0, 6.0, 4.0, 8.0] {
+            vec.push(*x);
+        }
+        let averages = vec
+            .windows::<f32>(3)
+            .unwrap()
+            .map(|window| window.iter().sum::<f32>() / 3.0)
+            .collect::<Vec<_>>();
+        assert_eq!(averages, [3.0, 4.0, 6.0]);
+
+        vec.push(1u8);
+        assert!(vec.windows::<f32>(3).is_err());
+    }
+
+    #[test]
+    fn retain_with() {
         let mut vec = AnyVec::new();
-        vec.reserve(8, 16);
-        assert!(vec.capacity(20) >= 6);
+        for i in 0..6 {
+            vec.push(i.to_string());
+            vec.push(i);
+        }
+
+        let mut pool = Vec::new();
+        vec.retain_with(|s: &String| s.as_str() < "3", |s| pool.push(s));
+        assert_eq!(pool, ["3", "4", "5"]);
+        assert_eq!(vec.len(), 9);
+        assert_eq!(vec.get::<String>(4).unwrap().unwrap(), "2");
+        assert_eq!(*vec.get::<i32>(8).unwrap().unwrap(), 5);
+    }
+
+    #[test]
+    fn predict_padding() {
+        let layouts = [(1, 1), (8, 8), (1, 1), (4, 4), (0, 8)];
+        assert_eq!(AnyVec::predict_padding(&layouts), 10);
+        assert_eq!(AnyVec::predict_padding(&[]), 0);
+
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(2u64);
+        vec.push(3u8);
+        vec.push(4u32);
+        vec.push([0u64; 0]);
+        assert_eq!(vec.data_len() - 14, AnyVec::predict_padding(&layouts));
+    }
+
+    #[test]
+    fn bytes_by_type() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(2u64);
+        vec.push(3u8);
+        vec.push([4u16; 3]);
+        vec.push(5u64);
+        vec.push(());
+
+        let bytes = vec.bytes_by_type();
+        assert_eq!(bytes.len(), 4);
+        assert_eq!(bytes[&TypeId::of::<u8>()], 2);
+        assert_eq!(bytes[&TypeId::of::<u64>()], 16);
+        assert_eq!(bytes[&TypeId::of::<[u16; 3]>()], 6);
+        assert_eq!(bytes[&TypeId::of::<()>()], 0);
+    }
+
+    #[test]
+    fn fragmentation_ratio() {
+        let mut vec = AnyVec::new();
+        assert_eq!(vec.fragmentation_ratio(), 0.0);
+        for x in 0..3u32 {
+            vec.push(x);
+        }
+        vec.push(3u64);
+        assert!((vec.fragmentation_ratio() - 4.0 / 24.0).abs() < 1e-9);
+
+        // The tail keeps its offset modulo 8, leaving 4 bytes at the front and 4 before the u64.
+        vec.remove(0);
+        assert_eq!(vec.data_len(), 24);
+        assert!((vec.fragmentation_ratio() - 8.0 / 24.0).abs() < 1e-9);
+
+        vec.compact();
+        assert_eq!(vec.fragmentation_ratio(), 0.0);
+    }
+
+    #[test]
+    fn recompute_offsets() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&1u32.to_ne_bytes());
+        data.extend_from_slice(&2u32.to_ne_bytes());
+        data.extend_from_slice(&3u16.to_ne_bytes());
+        data.resize(16, 0);
+        let meta = vec![
+            (TypeId::of::<u32>(), 0, 4),
+            (TypeId::of::<u32>(), 8, 4),
+            (TypeId::of::<u16>(), 14, 2),
+        ];
+        let mut vec = unsafe { AnyVec::from_raw_parts(data, meta) };
+        assert_ne!(*vec.get::<u32>(1).unwrap().unwrap(), 2);
+
+        unsafe { vec.recompute_offsets() };
+        assert_eq!(*vec.get::<u32>(0).unwrap().unwrap(), 1);
+        assert_eq!(*vec.get::<u32>(1).unwrap().unwrap(), 2);
+        assert_eq!(*vec.get::<u16>(2).unwrap().unwrap(), 3);
+        assert_eq!(vec.data_len(), 10);
+    }
+
+    #[test]
+    fn compact() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(2u32);
+        vec.push(3u16);
+        vec.push(4u64);
+        assert_eq!(vec.data_len(), 24);
+
+        // The tail only moves by multiples of 8, so removing the `u32` leaves a gap of 7 bytes.
+        vec.remove(1);
+        assert_eq!(vec.data_len(), 24);
+
+        assert_eq!(vec.compact(), 8);
+        assert_eq!(vec.data_len(), 16);
+        assert_eq!(vec.data.capacity(), 16);
+        assert_eq!(*vec.get::<u8>(0).unwrap().unwrap(), 1);
+        assert_eq!(*vec.get::<u16>(1).unwrap().unwrap(), 3);
+        assert_eq!(*vec.get::<u64>(2).unwrap().unwrap(), 4);
+        assert_eq!(vec.compact(), 0);
+    }
+
+    #[test]
+    fn element_ptr() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(TestData { a: 2, b: "Test" });
+
+        let (ptr, size) = vec.element_ptr(1).unwrap();
+        assert_eq!(size, mem::size_of::<TestData>());
+        assert_eq!(ptr as usize % mem::align_of::<TestData>(), 0);
+        let data = unsafe { &*(ptr as *const TestData) };
+        assert_eq!(data.a, vec.get::<TestData>(1).unwrap().unwrap().a);
+        assert_eq!(data.b, "Test");
+
+        let (ptr, size) = vec.element_ptr_mut(0).unwrap();
+        assert_eq!(size, 1);
+        unsafe { *ptr = 3 };
+        assert_eq!(*vec.get::<u8>(0).unwrap().unwrap(), 3);
+        assert!(vec.element_ptr(2).is_none());
+    }
+
+    #[test]
+    fn get_copy() {
+        let mut vec = AnyVec::new();
+        vec.push(7u64);
+        vec.push("Test");
+
+        let value = vec.get_copy::<u64>(0).unwrap().unwrap();
+        vec.clear();
+        vec.push(value + 1);
+        assert_eq!(vec.get_copy::<u64>(0), Ok(Some(8)));
+        assert_eq!(vec.get_copy::<u64>(1), Ok(None));
+        assert!(vec.get_copy::<u32>(0).is_err());
+    }
+
+    #[test]
+    fn get_mut_checked() {
+        let mut vec = AnyVec::new();
+        vec.push(1u32);
+        *vec.get_mut_checked::<u32>(0).unwrap() += 1;
+        assert_eq!(*vec.get::<u32>(0).unwrap().unwrap(), 2);
+
+        assert_eq!(
+            vec.get_mut_checked::<u32>(1).err(),
+            Some(AnyVecError::OutOfBounds { index: 1, len: 1 })
+        );
+        assert_eq!(
+            vec.get_mut_checked::<u8>(0).err(),
+            Some(AnyVecError::TypeMismatch {
+                expected: TypeId::of::<u8>(),
+                found: TypeId::of::<u32>(),
+            })
+        );
+    }
+
+    #[test]
+    fn transmute_element() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(u32::from_ne_bytes([1, 2, 3, 4]));
+        unsafe {
+            vec.transmute_element::<u32, [u8; 4]>(1).unwrap();
+        }
+        assert_eq!(vec.get::<[u8; 4]>(1).unwrap().unwrap(), &[1, 2, 3, 4]);
+
+        unsafe {
+            assert_eq!(
+                vec.transmute_element::<[u8; 4], u64>(1),
+                Err(AnyVecError::SizeMismatch {
+                    expected: 4,
+                    found: 8
+                })
+            );
+            assert_eq!(
+                vec.transmute_element::<u8, [u8; 4]>(1),
+                Err(AnyVecError::TypeMismatch {
+                    expected: TypeId::of::<u8>(),
+                    found: TypeId::of::<[u8; 4]>(),
+                })
+            );
+            vec.transmute_element::<[u8; 4], f32>(1).unwrap();
+        }
+        assert!(vec.is::<f32>(1).unwrap());
+
+        let mut vec = AnyVec::new();
+        vec.push([0u8; 4]);
+        unsafe {
+            assert_eq!(
+                vec.transmute_element::<[u8; 4], u32>(0),
+                Err(AnyVecError::Misaligned { index: 0, align: 4 })
+            );
+        }
+    }
+
+    #[test]
+    fn get_two_mut() {
+        let mut vec = AnyVec::new();
+        vec.push(TestData { a: 0, b: "Test" });
+        vec.push("Test");
+        vec.push(1u64);
+
+        {
+            let (data, value) = vec.get_two_mut::<TestData, u64>(0, 2).unwrap();
+            mem::swap(&mut data.a, value);
+        }
+        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 1);
+        assert_eq!(*vec.get::<u64>(2).unwrap().unwrap(), 0);
+
+        assert_eq!(
+            vec.get_two_mut::<u64, u64>(2, 2).err(),
+            Some(AnyVecError::DuplicateIndex(2))
+        );
+        assert_eq!(
+            vec.get_two_mut::<TestData, u64>(0, 1).err(),
+            Some(AnyVecError::TypeMismatch {
+                expected: TypeId::of::<u64>(),
+                found: TypeId::of::<&str>(),
+            })
+        );
+        assert_eq!(
+            vec.get_two_mut::<TestData, u64>(0, 3).err(),
+            Some(AnyVecError::OutOfBounds { index: 3, len: 3 })
+        );
+    }
+
+    #[test]
+    fn get_ref_and_mut() {
+        let mut vec = AnyVec::new();
+        vec.push(3u32);
+        vec.push("counter");
+        vec.push(10u64);
+
+        {
+            let (step, counter) = vec.get_ref_and_mut::<u32, u64>(0, 2).unwrap();
+            *counter += u64::from(*step);
+        }
+        assert_eq!(*vec.get::<u64>(2).unwrap().unwrap(), 13);
+
+        assert_eq!(
+            vec.get_ref_and_mut::<u64, u64>(2, 2).err(),
+            Some(AnyVecError::DuplicateIndex(2))
+        );
+        assert_eq!(
+            vec.get_ref_and_mut::<u32, u32>(0, 1).err(),
+            Some(AnyVecError::TypeMismatch {
+                expected: TypeId::of::<u32>(),
+                found: TypeId::of::<&str>(),
+            })
+        );
+    }
+
+    #[test]
+    fn neighbors() {
+        let mut vec = AnyVec::new();
+        for x in [1.0f64, 2.0, 3.0] {
+            vec.push(x);
+        }
+
+        assert_eq!(vec.neighbors::<f64>(1).unwrap(), (Some(&1.0), &2.0, Some(&3.0)));
+        assert_eq!(vec.neighbors::<f64>(0).unwrap(), (None, &1.0, Some(&2.0)));
+        assert_eq!(vec.neighbors::<f64>(2).unwrap(), (Some(&2.0), &3.0, None));
+        assert_eq!(
+            vec.neighbors::<f64>(3).err(),
+            Some(AnyVecError::OutOfBounds { index: 3, len: 3 })
+        );
+
+        vec.push(4u8);
+        assert_eq!(
+            vec.neighbors::<f64>(2).err(),
+            Some(AnyVecError::TypeMismatch {
+                expected: TypeId::of::<f64>(),
+                found: TypeId::of::<u8>(),
+            })
+        );
+    }
+
+    #[test]
+    fn get_disjoint_mut() {
+        let mut vec = AnyVec::new();
+        for x in 0..5u32 {
+            vec.push(x);
+        }
+        vec.push("Test");
+
+        {
+            let [a, b, c] = vec.get_disjoint_mut::<u32, 3>([4, 0, 2]).unwrap();
+            mem::swap(a, b);
+            *c += 10;
+        }
+        assert_eq!(*vec.get::<u32>(0).unwrap().unwrap(), 4);
+        assert_eq!(*vec.get::<u32>(2).unwrap().unwrap(), 12);
+        assert_eq!(*vec.get::<u32>(4).unwrap().unwrap(), 0);
+
+        assert_eq!(
+            vec.get_disjoint_mut::<u32, 3>([1, 3, 1]).err(),
+            Some(AnyVecError::DuplicateIndex(1))
+        );
+        assert_eq!(
+            vec.get_disjoint_mut::<u32, 2>([1, 5]).err(),
+            Some(AnyVecError::TypeMismatch {
+                expected: TypeId::of::<u32>(),
+                found: TypeId::of::<&str>(),
+            })
+        );
+        assert_eq!(
+            vec.get_disjoint_mut::<u32, 1>([6]).err(),
+            Some(AnyVecError::OutOfBounds { index: 6, len: 6 })
+        );
+    }
+
+    #[test]
+    fn merge_sorted() {
+        let mut a = AnyVec::new();
+        let mut b = AnyVec::new();
+        for x in [1u32, 3, 5] {
+            a.push(x);
+            b.push(x + 1);
+        }
+        let merged = a.merge_sorted(b, |x: &u32, y| x.cmp(y)).unwrap();
+        assert_eq!(merged.slice::<u32>(0..6).unwrap(), &[1, 2, 3, 4, 5, 6]);
+
+        let rc = Rc::new(());
+        let mut a = AnyVec::new();
+        a.push((1, rc.clone()));
+        let mut b = AnyVec::new();
+        b.push((0, rc.clone()));
+        b.push((1, rc.clone()));
+        let merged = a
+            .merge_sorted(b, |x: &(i32, Rc<()>), y| x.0.cmp(&y.0))
+            .unwrap();
+        let keys: Vec<i32> = merged.iter_type::<(i32, Rc<()>)>().map(|x| x.0).collect();
+        assert_eq!(keys, [0, 1, 1]);
+        mem::drop(merged);
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        let mut a = AnyVec::new();
+        a.push(1u32);
+        let mut b = AnyVec::new();
+        b.push(1u8);
+        assert_eq!(
+            a.merge_sorted(b, |x: &u32, y| x.cmp(y)).err(),
+            Some(AnyVecError::TypeMismatch {
+                expected: TypeId::of::<u32>(),
+                found: TypeId::of::<u8>(),
+            })
+        );
+    }
+
+    #[test]
+    fn partition_by_type() {
+        let rc = Rc::new(());
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push("a");
+        vec.push(2u16);
+        vec.push(3u8);
+        vec.push("b");
+        vec.push(rc.clone());
+
+        let mut buckets = vec.partition_by_type();
+        assert_eq!(buckets.len(), 4);
+        let bytes: Vec<u8> = buckets[&TypeId::of::<u8>()].iter_type::<u8>().copied().collect();
+        assert_eq!(bytes, [1, 3]);
+        assert_eq!(buckets[&TypeId::of::<u16>()].len(), 1);
+        assert_eq!(*buckets[&TypeId::of::<u16>()].get::<u16>(0).unwrap().unwrap(), 2);
+        let strs: Vec<&&str> = buckets[&TypeId::of::<&str>()].collect_type();
+        assert_eq!(strs, [&"a", &"b"]);
+
+        assert_eq!(Rc::strong_count(&rc), 2);
+        buckets.remove(&TypeId::of::<Rc<()>>());
+        assert_eq!(Rc::strong_count(&rc), 1);
     }
 
     #[test]
-    fn reserve_exact() {
-        let mut vec = AnyVec::new();
-        vec.reserve_exact(8, 64);
-        assert!(vec.capacity(64) >= 8);
-        let mut vec = AnyVec::new();
-        vec.reserve_exact(8, 64);
-        assert!(vec.capacity(32) >= 8);
-        let mut vec = AnyVec::new();
-        vec.reserve_exact(16, 64);
-        assert!(vec.capacity(64) >= 16);
+    fn binary_search_by() {
         let mut vec = AnyVec::new();
-        vec.reserve_exact(16, 32);
-        assert!(vec.capacity(64) >= 8);
+        assert_eq!(vec.binary_search_by(|x: &i64| x.cmp(&0)), Ok(Err(0)));
+
+        for x in &[-4i64, 0, 3, 7, 12] {
+            vec.push(*x);
+        }
+        assert_eq!(vec.binary_search_by(|x: &i64| x.cmp(&-4)), Ok(Ok(0)));
+        assert_eq!(vec.binary_search_by(|x: &i64| x.cmp(&7)), Ok(Ok(3)));
+        assert_eq!(vec.binary_search_by(|x: &i64| x.cmp(&1)), Ok(Err(2)));
+        assert_eq!(vec.binary_search_by(|x: &i64| x.cmp(&20)), Ok(Err(5)));
+
+        vec.push("Test");
+        assert_eq!(
+            vec.binary_search_by(|x: &i64| x.cmp(&7)),
+            Err(AnyVecError::TypeMismatch {
+                expected: TypeId::of::<i64>(),
+                found: TypeId::of::<&str>(),
+            })
+        );
+    }
+
+    #[test]
+    fn sort_by() {
         let mut vec = AnyVec::new();
-        vec.reserve_exact(8, 20);
-        assert!(vec.capacity(16) >= 8);
+        for x in &[5u32, 1, 4, 2, 3, 0] {
+            vec.push(*x);
+        }
+        vec.sort_by(|a: &u32, b: &u32| a.cmp(b)).unwrap();
+        for i in 0..6 {
+            assert_eq!(*vec.get::<u32>(i).unwrap().unwrap(), i as u32);
+        }
+
+        vec.push(6u64);
+        assert!(vec.sort_by(|a: &u32, b: &u32| a.cmp(b)).is_err());
+    }
+
+    #[test]
+    fn map_in_place() {
         let mut vec = AnyVec::new();
-        vec.reserve_exact(8, 16);
-        assert!(vec.capacity(20) >= 6);
+        vec.push(1.5f64);
+        vec.push("Test");
+        vec.push(-2.0f64);
+
+        vec.map_in_place(|x: &mut f64| *x *= 2.0);
+        assert_eq!(*vec.get::<f64>(0).unwrap().unwrap(), 3.0);
+        assert_eq!(*vec.get::<&str>(1).unwrap().unwrap(), "Test");
+        assert_eq!(*vec.get::<f64>(2).unwrap().unwrap(), -4.0);
     }
 
     #[test]
-    fn shrink_to_fit() {
-        let mut vec = AnyVec::with_capacity(4, 1);
-        vec.push(0 as u8);
-        vec.push(1 as u8);
-        vec.shrink_to_fit();
-        assert_eq!(vec.capacity(1), 2);
+    #[cfg(feature = "parallel")]
+    fn par_map_in_place() {
+        fn build() -> AnyVec {
+            let mut vec = AnyVec::new();
+            vec.push("start");
+            vec.extend((0..10_000).map(|i| f64::from(i) / 3.0));
+            vec.push(7u8);
+            vec
+        }
 
-        let mut vec = AnyVec::with_capacity(8, 2);
-        vec.push(0 as u16);
-        vec.push(1 as u16);
-        vec.push(2 as u16);
-        vec.shrink_to_fit();
-        assert_eq!(vec.capacity(2), 3);
+        let mut parallel = build();
+        parallel.par_map_in_place(|x: &mut f64| *x *= 2.0);
+        let mut sequential = build();
+        sequential.map_in_place(|x: &mut f64| *x *= 2.0);
+        assert_eq!(parallel.slice::<f64>(1..10_001), sequential.slice::<f64>(1..10_001));
+        assert_eq!(*parallel.get::<f64>(3).unwrap().unwrap(), 4.0 / 3.0);
+        assert_eq!(*parallel.get::<u8>(10_001).unwrap().unwrap(), 7);
 
-        let mut vec = AnyVec::with_capacity(8, mem::size_of::<TestData>());
-        vec.push(TestData { a: 0, b: "Test" });
-        vec.push(TestData { a: 1, b: "Test" });
-        vec.shrink_to_fit();
-        assert_eq!(vec.capacity(mem::size_of::<TestData>()), 2);
+        let mut small = AnyVec::new();
+        small.extend([1.0f64, 2.0]);
+        small.par_map_in_place(|x: &mut f64| *x *= 2.0);
+        assert_eq!(small.slice::<f64>(0..2), Ok(&[2.0, 4.0][..]));
+        AnyVec::new().par_map_in_place(|x: &mut f64| *x *= 2.0);
     }
 
     #[test]
-    fn truncate() {
+    fn runs() {
         let mut vec = AnyVec::new();
-        vec.push(0);
-        vec.push(1);
-        vec.push(2);
-        vec.push(3);
-        vec.truncate(2);
-        assert_eq!(vec.len(), 2);
+        assert_eq!(vec.runs().count(), 0);
 
-        let mut vec = AnyVec::new();
-        vec.push(TestData { a: 0, b: "Test" });
-        vec.push(TestData { a: 1, b: "Test" });
-        vec.push(TestData { a: 2, b: "Test" });
-        vec.push(TestData { a: 3, b: "Test" });
-        vec.truncate(3);
-        assert_eq!(vec.len(), 3);
+        vec.push(0u8);
+        vec.push(1u8);
+        vec.push("Test");
+        vec.push(2u8);
+        let runs: Vec<_> = vec.runs().collect();
+        assert_eq!(
+            runs,
+            vec![
+                (TypeId::of::<u8>(), 0..2),
+                (TypeId::of::<&str>(), 2..3),
+                (TypeId::of::<u8>(), 3..4),
+            ]
+        );
     }
 
     #[test]
-    fn insert() {
-        let mut vec = AnyVec::new();
-        vec.insert(0, TestData { a: 1, b: "Test" });
-        vec.insert(1, TestData { a: 2, b: "Test" });
-        vec.insert(0, TestData { a: 0, b: "Test" });
-        vec.insert(3, TestData { a: 3, b: "Test" });
-        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
-        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().b, "Test");
-        assert_eq!(vec.get::<TestData>(1).unwrap().unwrap().a, 1);
-        assert_eq!(vec.get::<TestData>(2).unwrap().unwrap().a, 2);
-        assert_eq!(vec.get::<TestData>(3).unwrap().unwrap().a, 3);
+    fn raw_iter() {
+        fn hash(vec: &AnyVec) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            for (type_id, bytes) in vec.raw_iter() {
+                type_id.hash(&mut hasher);
+                unsafe { assume_init(bytes) }.hash(&mut hasher);
+            }
+            hasher.finish()
+        }
+
+        let mut vec1 = AnyVec::new();
+        let mut vec2 = AnyVec::new();
+        for vec in [&mut vec1, &mut vec2].iter_mut() {
+            vec.push(1u8);
+            vec.push(0x0102_0304u32);
+            vec.push([5u16, 6]);
+        }
+
+        let raw: Vec<_> = vec1.raw_iter().collect();
+        assert_eq!(raw[0].0, TypeId::of::<u8>());
+        unsafe {
+            assert_eq!(assume_init(raw[0].1), [1u8]);
+            assert_eq!(assume_init(raw[1].1), 0x0102_0304u32.to_ne_bytes());
+        }
+        assert_eq!(raw[2].1.len(), 4);
+        assert_eq!(hash(&vec1), hash(&vec2));
+
+        vec2.push(0u8);
+        assert_ne!(hash(&vec1), hash(&vec2));
     }
 
     #[test]
-    fn remove_and_return() {
+    fn enumerate_typed() {
         let mut vec = AnyVec::new();
-        vec.insert(0, TestData { a: 1, b: "Test" });
-        vec.insert(1, TestData { a: 2, b: "Test" });
-        vec.insert(0, TestData { a: 0, b: "Test" });
-        vec.insert(3, TestData { a: 3, b: "Test" });
+        vec.push(1u8);
+        vec.push(2u32);
+        vec.push(3u16);
 
-        assert_eq!(vec.remove_and_return::<TestData>(2).unwrap().a, 2);
-        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
-        assert_eq!(vec.remove_and_return::<TestData>(1).unwrap().a, 1);
-        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
-        assert_eq!(vec.remove_and_return::<TestData>(0).unwrap().a, 0);
-        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 3);
+        let entries: Vec<_> = vec.enumerate_typed().collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!((entries[0].0, entries[0].1), (0, TypeId::of::<u8>()));
+        assert_eq!((entries[1].0, entries[1].1), (1, TypeId::of::<u32>()));
+        unsafe {
+            assert_eq!(assume_init(entries[0].2), [1u8]);
+            assert_eq!(assume_init(entries[1].2), 2u32.to_ne_bytes());
+        }
+        assert_eq!((entries[2].0, entries[2].1), (2, TypeId::of::<u16>()));
     }
 
     #[test]
-    fn remove() {
-        let mut vec = AnyVec::new();
-        vec.insert(0, TestData { a: 1, b: "Test" });
-        vec.insert(1, TestData { a: 2, b: "Test" });
-        vec.insert(0, TestData { a: 0, b: "Test" });
-        vec.insert(3, TestData { a: 3, b: "Test" });
+    fn content_hash() {
+        fn hash(vec: &AnyVec) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            unsafe { vec.content_hash(&mut hasher) };
+            hasher.finish()
+        }
 
-        vec.remove(2);
-        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
-        vec.remove(1);
-        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
-        vec.remove(0);
-        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 3);
+        let mut vec1 = AnyVec::new();
+        let mut vec2 = AnyVec::new();
+        for vec in [&mut vec1, &mut vec2].iter_mut() {
+            vec.push(7u64);
+            vec.push([1u8, 2, 3]);
+        }
+        assert_eq!(hash(&vec1), hash(&vec2));
+
+        vec2.get_mut::<[u8; 3]>(1).unwrap().unwrap()[2] = 4;
+        assert_ne!(hash(&vec1), hash(&vec2));
     }
 
     #[test]
-    fn is() {
+    fn type_mismatch_found() {
         let mut vec = AnyVec::new();
-        vec.push(TestData { a: 0, b: "Test" });
-        vec.push("Test");
-        vec.push(0 as u8);
+        vec.push(0u8);
+        vec.push(1u16);
+        vec.push(2u32);
+        let mismatch = |found| AnyVecError::TypeMismatch {
+            expected: TypeId::of::<u64>(),
+            found,
+        };
 
-        assert!(vec.is::<TestData>(0).unwrap());
-        assert!(vec.is::<&str>(1).unwrap());
-        assert!(!vec.is::<TestData>(1).unwrap());
-        assert!(vec.is::<u8>(2).unwrap());
+        assert_eq!(vec.get::<u64>(0).unwrap_err(), mismatch(TypeId::of::<u8>()));
+        assert_eq!(vec.get_mut::<u64>(1).unwrap_err(), mismatch(TypeId::of::<u16>()));
+        assert_eq!(
+            vec.remove_and_return::<u64>(0).unwrap_err(),
+            mismatch(TypeId::of::<u8>())
+        );
+        assert_eq!(vec.pop::<u64>().unwrap_err(), mismatch(TypeId::of::<u32>()));
+        assert_eq!(vec.len(), 3);
     }
 
     #[test]
-    fn get() {
+    fn zero_sized() {
         let mut vec = AnyVec::new();
-        vec.push(TestData { a: 0, b: "Test" });
-        vec.push(TestData { a: 0, b: "Test" });
-        vec.push(TestData { a: 0, b: "Test" });
-        vec.push(TestData { a: 0, b: "Test" });
+        for _ in 0..1000 {
+            vec.push(());
+        }
+        assert_eq!(vec.len(), 1000);
+        assert_eq!(vec.data.capacity(), 0);
+        for _ in 0..1000 {
+            assert_eq!(vec.pop::<()>(), Ok(Some(())));
+        }
+        assert!(vec.is_empty());
 
-        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 0);
-        vec.get_mut::<TestData>(0).unwrap().unwrap().a += 1;
-        assert_eq!(vec.get::<TestData>(0).unwrap().unwrap().a, 1);
-        assert_eq!(vec.get::<TestData>(2).unwrap().unwrap().a, 0);
+        vec.push(1u64);
+        vec.push(2u8);
+        vec.push([0u64; 0]);
+        vec.insert(1, [0u32; 0]);
+        assert_eq!(vec.data_len(), 16);
+        vec.debug_assert_consistent();
+        assert_eq!(vec.pop::<[u64; 0]>(), Ok(Some([])));
+        assert_eq!(vec.data_len(), 9);
+        vec.remove(1);
+        assert_eq!(*vec.get::<u8>(1).unwrap().unwrap(), 2);
+        vec.debug_assert_consistent();
     }
 
     #[test]
@@ -534,6 +5327,38 @@ mod tests {
         assert_eq!(vec.pop::<TestData>().unwrap().unwrap().a, 3);
         assert_eq!(vec.pop::<TestData>().unwrap().unwrap().a, 1);
         assert_eq!(vec.pop::<TestData>().unwrap().unwrap().a, 0);
+        assert!(vec.pop::<TestData>().unwrap().is_none());
+    }
+
+    #[test]
+    fn pop_padded() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(String::from("Test"));
+        assert!(vec.pop::<u8>().is_err());
+        assert_eq!(vec.len(), 2);
+
+        assert_eq!(vec.pop::<String>().unwrap().unwrap(), "Test");
+        assert_eq!(vec.data_len(), 1);
+        vec.debug_assert_consistent();
+    }
+
+    #[test]
+    fn send_sync() {
+        let mut vec = AnyVec::new();
+        vec.push_sync(0u32);
+        vec.push_send(Cell::new(1u32));
+        assert!(vec.is_send());
+        assert!(!vec.is_sync());
+
+        let mut vec = vec.into_sendable().unwrap();
+        vec.push(String::from("Test"));
+        let mut vec = thread::spawn(move || vec).join().unwrap().into_inner();
+        assert_eq!(vec.get::<Cell<u32>>(1).unwrap().unwrap().get(), 1);
+
+        vec.push(Rc::new(()));
+        assert!(!vec.is_send());
+        assert!(vec.into_sendable().is_err());
     }
 
     #[test]
@@ -556,6 +5381,141 @@ mod tests {
         assert!(vec1.is::<&str>(6).unwrap());
     }
 
+    #[test]
+    fn append_cloned() {
+        let mut vec1 = AnyVec::new();
+        vec1.push_cloneable(0u8);
+
+        let mut vec2 = AnyVec::new();
+        vec2.push_cloneable(String::from("Test"));
+        vec2.push_cloneable(1u32);
+
+        vec1.append_cloned(&vec2).unwrap();
+        vec1.append_cloned(&vec2).unwrap();
+        assert_eq!(vec1.len(), 5);
+        assert_eq!(vec1.get::<String>(1).unwrap().unwrap(), "Test");
+        assert_eq!(*vec1.get::<u32>(2).unwrap().unwrap(), 1);
+        assert_eq!(vec1.get::<String>(3).unwrap().unwrap(), "Test");
+        assert_eq!(*vec1.get::<u32>(4).unwrap().unwrap(), 1);
+        assert_eq!(vec2.len(), 2);
+        assert_eq!(vec2.get::<String>(0).unwrap().unwrap(), "Test");
+
+        vec2.push(2u32);
+        assert_eq!(vec1.append_cloned(&vec2), Err(AnyVecError::NotCloneable(2)));
+        assert_eq!(vec1.len(), 5);
+    }
+
+    #[test]
+    fn freeze_thaw() {
+        let mut vec = AnyVec::new();
+        vec.push(1u8);
+        vec.push(2u64);
+        vec.push(String::from("Test"));
+        vec.push(3u64);
+        vec.remove(0);
+
+        let frozen = vec.freeze();
+        assert_eq!(frozen.len(), 3);
+        assert!(!frozen.is_empty());
+        assert_eq!(frozen.get::<String>(1).unwrap().unwrap(), "Test");
+        assert_eq!(frozen.get::<u64>(3), Ok(None));
+        assert_eq!(frozen.iter_type::<u64>().sum::<u64>(), 5);
+
+        let mut vec = frozen.thaw();
+        assert_eq!(vec.fragmentation_ratio(), 0.0);
+        assert_eq!(vec.data.capacity(), vec.data_len());
+        vec.push(4u64);
+        assert_eq!(vec.len(), 4);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut vec = AnyVec::new();
+        vec.push_cloneable(String::from("Test"));
+        vec.push_cloneable(1u64);
+        let snapshot = vec.snapshot().unwrap();
+        assert_eq!(snapshot.len(), 2);
+
+        vec.get_mut::<String>(0).unwrap().unwrap().push_str("ed");
+        vec.push_cloneable(2u8);
+        vec.remove(1);
+        vec.restore(snapshot);
+        assert_eq!(vec.len(), 2);
+        assert_eq!(vec.get::<String>(0).unwrap().unwrap(), "Test");
+        assert_eq!(*vec.get::<u64>(1).unwrap().unwrap(), 1);
+        vec.debug_assert_consistent();
+
+        vec.push(3u32);
+        assert_eq!(vec.snapshot().unwrap_err(), AnyVecError::NotCloneable(2));
+    }
+
+    #[test]
+    fn restore_keeps_growth() {
+        let mut vec = AnyVec::new();
+        vec.set_growth(Growth::Exact);
+        vec.push_cloneable(1u64);
+        let snapshot = vec.snapshot().unwrap();
+        vec.restore(snapshot);
+
+        for i in 0..10u64 {
+            vec.push(i);
+        }
+        assert_eq!(vec.data.capacity(), vec.data.len());
+    }
+
+    #[test]
+    fn split_keeps_growth() {
+        let mut vec = AnyVec::new();
+        vec.set_growth(Growth::Exact);
+        vec.extend(0..4u64);
+        vec.push(1u8);
+
+        assert_eq!(vec.split_off(3).data.growth, Growth::Exact);
+        assert_eq!(vec.extract_if(|x: &u64| *x == 0).data.growth, Growth::Exact);
+        assert_eq!(vec.remove_indices(&[0]).data.growth, Growth::Exact);
+        for bucket in vec.partition_by_type().values() {
+            assert_eq!(bucket.data.growth, Growth::Exact);
+        }
+    }
+
+    #[test]
+    fn push_named() {
+        let mut vec = AnyVec::new();
+        vec.push_named(1u32);
+        vec.push(2u32);
+        vec.push_named(String::from("Test"));
+        assert_eq!(vec.type_name_of(0), Some("u32"));
+        assert_eq!(vec.type_name_of(1), None);
+        assert_eq!(vec.type_name_of(2), Some(std::any::type_name::<String>()));
+        assert_eq!(vec.type_name_of(3), None);
+        assert_eq!(vec.get::<String>(2).unwrap().unwrap(), "Test");
+    }
+
+    #[test]
+    fn push_handle() {
+        let mut vec = AnyVec::new();
+        vec.push(0u8);
+        let a = vec.push_handle(String::from("a"));
+        let b = vec.push_handle(1u32);
+        assert_eq!(vec.get_by_handle::<String>(a).unwrap(), "a");
+        assert_eq!(vec.get_by_handle::<u32>(a), None);
+
+        vec.remove(0);
+        assert_eq!(vec.index_of_handle(b), Some(1));
+        assert_eq!(*vec.get_by_handle::<u32>(b).unwrap(), 1);
+
+        vec.remove(0);
+        assert_eq!(vec.get_by_handle::<String>(a), None);
+        for i in 0..4u32 {
+            vec.push_handle(i);
+        }
+        assert_eq!(vec.get_by_handle::<String>(a), None);
+        assert_eq!(vec.get_by_handle::<u32>(b), Some(&1));
+
+        let other = vec.split_off(0);
+        assert_eq!(other.index_of_handle(b), None);
+    }
+
     #[test]
     fn clear() {
         let mut vec = AnyVec::new();
@@ -567,6 +5527,97 @@ mod tests {
         assert!(vec.is_empty());
     }
 
+    #[test]
+    fn drop_observer() {
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let mut vec = AnyVec::new();
+        let observed = dropped.clone();
+        vec.set_drop_observer(Box::new(move |type_id| observed.borrow_mut().push(type_id)));
+        vec.push(1u8);
+        vec.push(String::from("Test"));
+        vec.push(2u32);
+        vec.push(3u8);
+
+        vec.remove(2);
+        assert_eq!(vec.pop::<u8>(), Ok(Some(3)));
+        assert_eq!(*dropped.borrow(), [TypeId::of::<u32>()]);
+        mem::drop(vec);
+        assert_eq!(
+            *dropped.borrow(),
+            [TypeId::of::<u32>(), TypeId::of::<u8>(), TypeId::of::<String>()]
+        );
+
+        let mut vec = AnyVec::new();
+        vec.push_send(1u8);
+        vec.set_drop_observer(Box::new(|_| panic!("observed")));
+        let mut vec = vec.into_sendable().unwrap_err();
+        assert!(vec.take_drop_observer().is_some());
+        assert!(vec.into_sendable().is_ok());
+    }
+
+    #[test]
+    fn clear_type() {
+        let rc = Rc::new(());
+        let mut vec = AnyVec::new();
+        vec.push(0u32);
+        vec.push("a");
+        vec.push(rc.clone());
+        vec.push(1u32);
+        vec.push("b");
+        vec.push(2u32);
+
+        assert_eq!(vec.clear_type::<u32>(), 3);
+        assert_eq!(vec.len(), 3);
+        assert_eq!(*vec.get::<&str>(0).unwrap().unwrap(), "a");
+        assert_eq!(*vec.get::<&str>(2).unwrap().unwrap(), "b");
+
+        assert_eq!(vec.clear_type::<Rc<()>>(), 1);
+        assert_eq!(Rc::strong_count(&rc), 1);
+        assert_eq!(vec.clear_type::<u32>(), 0);
+        assert_eq!(vec.len(), 2);
+    }
+
+    #[test]
+    fn retain_any() {
+        let rc = Rc::new(());
+        let mut vec = AnyVec::new();
+        vec.push(0u8);
+        vec.push("a");
+        vec.push(rc.clone());
+        vec.push(1u8);
+        vec.push(2u16);
+        vec.push("b");
+
+        let allowed = [TypeId::of::<u8>(), TypeId::of::<&str>()];
+        vec.retain_any(|_, type_id| allowed.contains(&type_id));
+        assert_eq!(vec.len(), 4);
+        assert_eq!(Rc::strong_count(&rc), 1);
+        assert_eq!(*vec.get::<u8>(0).unwrap().unwrap(), 0);
+        assert_eq!(*vec.get::<&str>(1).unwrap().unwrap(), "a");
+        assert_eq!(*vec.get::<u8>(2).unwrap().unwrap(), 1);
+        assert_eq!(*vec.get::<&str>(3).unwrap().unwrap(), "b");
+
+        vec.retain_any(|index, _| index % 2 == 0);
+        assert_eq!(vec.len(), 2);
+        assert!(vec.is::<u8>(0).unwrap());
+        assert!(vec.is::<u8>(1).unwrap());
+    }
+
+    #[test]
+    fn drop() {
+        let rc = Rc::new(());
+        let mut vec = AnyVec::new();
+        for _ in 0..4 {
+            vec.push(rc.clone());
+        }
+        vec.remove(0);
+        assert_eq!(Rc::strong_count(&rc), 4);
+        vec.truncate(2);
+        assert_eq!(Rc::strong_count(&rc), 3);
+        mem::drop(vec);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
     #[test]
     fn split_off() {
         let mut vec1 = AnyVec::new();
@@ -582,4 +5633,110 @@ mod tests {
         assert_eq!(vec2.len(), 2);
         assert_eq!(vec2.get::<TestData>(0).unwrap().unwrap().a, 4);
     }
+
+    /// Exercises the unsafe byte moves of `insert`, `remove`, `append` and `split_off` with
+    /// elements of several sizes and alignments; run under `cargo miri test` to check for UB.
+    mod layout {
+        use super::*;
+
+        /// The kinds of elements: sizes 1, 3, 8 and an `Rc`, which checks the drops.
+        const KINDS: usize = 4;
+
+        fn push_kind(vec: &mut AnyVec, index: usize, kind: usize, value: u8, rc: &Rc<()>) {
+            match kind {
+                0 => vec.insert(index, value),
+                1 => vec.insert(index, [value; 3]),
+                2 => vec.insert(index, u64::from(value) << 40),
+                _ => vec.insert(index, (value, rc.clone())),
+            }
+        }
+
+        fn assert_model(vec: &AnyVec, model: &[(usize, u8)]) {
+            vec.debug_assert_consistent();
+            assert_eq!(vec.len(), model.len());
+            for (index, &(kind, value)) in model.iter().enumerate() {
+                match kind {
+                    0 => assert_eq!(vec.get::<u8>(index), Ok(Some(&value))),
+                    1 => assert_eq!(vec.get::<[u8; 3]>(index), Ok(Some(&[value; 3]))),
+                    2 => assert_eq!(
+                        vec.get::<u64>(index),
+                        Ok(Some(&(u64::from(value) << 40)))
+                    ),
+                    _ => assert_eq!(vec.get::<(u8, Rc<()>)>(index).unwrap().unwrap().0, value),
+                }
+            }
+        }
+
+        fn build(len: usize, first_kind: usize, rc: &Rc<()>) -> (AnyVec, Vec<(usize, u8)>) {
+            let mut vec = AnyVec::new();
+            let mut model = Vec::new();
+            for i in 0..len {
+                let kind = (first_kind + i) % KINDS;
+                push_kind(&mut vec, i, kind, i as u8, rc);
+                model.push((kind, i as u8));
+            }
+            (vec, model)
+        }
+
+        #[test]
+        fn insert() {
+            let rc = Rc::new(());
+            for first_kind in 0..KINDS {
+                for kind in 0..KINDS {
+                    for &index in &[0, 3, 6] {
+                        let (mut vec, mut model) = build(6, first_kind, &rc);
+                        push_kind(&mut vec, index, kind, 100, &rc);
+                        model.insert(index, (kind, 100));
+                        assert_model(&vec, &model);
+                    }
+                }
+            }
+            assert_eq!(Rc::strong_count(&rc), 1);
+        }
+
+        #[test]
+        fn remove() {
+            let rc = Rc::new(());
+            for first_kind in 0..KINDS {
+                for index in 0..7 {
+                    let (mut vec, mut model) = build(7, first_kind, &rc);
+                    vec.remove(index);
+                    model.remove(index);
+                    assert_model(&vec, &model);
+                }
+            }
+            assert_eq!(Rc::strong_count(&rc), 1);
+        }
+
+        #[test]
+        fn append() {
+            let rc = Rc::new(());
+            for left in 0..4 {
+                for first_kind in 0..KINDS {
+                    let (mut vec, mut model) = build(left, 0, &rc);
+                    let (mut other, other_model) = build(5, first_kind, &rc);
+                    vec.append(&mut other);
+                    model.extend(other_model);
+                    assert_model(&vec, &model);
+                    assert_model(&other, &[]);
+                }
+            }
+            assert_eq!(Rc::strong_count(&rc), 1);
+        }
+
+        #[test]
+        fn split_off() {
+            let rc = Rc::new(());
+            for first_kind in 0..KINDS {
+                for at in 0..=7 {
+                    let (mut vec, mut model) = build(7, first_kind, &rc);
+                    let other = vec.split_off(at);
+                    let other_model = model.split_off(at);
+                    assert_model(&vec, &model);
+                    assert_model(&other, &other_model);
+                }
+            }
+            assert_eq!(Rc::strong_count(&rc), 1);
+        }
+    }
 }
@@ -0,0 +1,11 @@
+extern crate anyvec;
+
+use anyvec::AnyVec;
+use std::rc::Rc;
+use std::thread;
+
+fn main() {
+    let mut vec = AnyVec::new();
+    vec.push(Rc::new(()));
+    thread::spawn(move || vec.len());
+}